@@ -0,0 +1,129 @@
+use super::board::Board;
+use super::input::Input;
+
+// weights from Yiyuan Lee's "Tetris AI – The (Near) Perfect Bot"
+const HEIGHT_WEIGHT: f32 = -0.510066;
+const LINES_WEIGHT: f32 = 0.760666;
+const HOLES_WEIGHT: f32 = -0.35663;
+const BUMPINESS_WEIGHT: f32 = -0.184483;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Placement {
+    x: i32,
+    rotation: i8,
+}
+
+pub struct Ai {
+    move_frames: u32,
+    timer: u32,
+}
+
+impl Ai {
+    // move_frames is how many frames the AI waits between inputs
+    pub fn new(move_frames: u32) -> Self {
+        Self {
+            move_frames: move_frames.max(1),
+            timer: 0,
+        }
+    }
+
+    pub fn tick(&mut self, board: &Board) -> Input {
+        // waiting out a line clear or entry delay
+        if board.current_tetrimino.is_none() {
+            return Input::NONE;
+        }
+
+        self.timer += 1;
+        if self.timer < self.move_frames {
+            return Input::NONE;
+        }
+        self.timer = 0;
+
+        let Some(target) = best_placement(board) else {
+            return Input::HARD_DROP;
+        };
+
+        if board.current_tetrimino_rotation != target.rotation {
+            Input::ROTATE_RIGHT
+        } else if board.current_tetrimino_pos_x > target.x {
+            Input::LEFT
+        } else if board.current_tetrimino_pos_x < target.x {
+            Input::RIGHT
+        } else {
+            Input::HARD_DROP
+        }
+    }
+}
+
+fn best_placement(board: &Board) -> Option<Placement> {
+    let width = board.width() as i32;
+
+    let mut best: Option<(f32, Placement)> = None;
+    for rotations in 0..4 {
+        for x in -2..width {
+            let mut sim = board.clone();
+            // clear rows straight away so the result can be scored
+            sim.set_entry_delay(false);
+
+            for _ in 0..rotations {
+                sim.rotate_right();
+            }
+            let rotation = sim.current_tetrimino_rotation;
+
+            while sim.current_tetrimino_pos_x > x && sim.move_left() {}
+            while sim.current_tetrimino_pos_x < x && sim.move_right() {}
+            if sim.current_tetrimino_pos_x != x {
+                continue;
+            }
+
+            sim.hard_drop();
+            let lines = sim
+                .take_events()
+                .iter()
+                .map(|event| match event {
+                    super::board::BoardEvent::Locked { lines, .. } => *lines,
+                    _ => 0,
+                })
+                .sum();
+
+            let score = evaluate(&sim, lines);
+            if best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, Placement { x, rotation }));
+            }
+        }
+    }
+
+    best.map(|(_, placement)| placement)
+}
+
+fn evaluate(board: &Board, lines: usize) -> f32 {
+    let heights: Vec<usize> = (0..board.width())
+        .map(|x| {
+            (0..board.height())
+                .rev()
+                .find(|&y| !board.get_block_at(x, y).unwrap().is_empty())
+                .map_or(0, |y| y + 1)
+        })
+        .collect();
+
+    let holes: usize = heights
+        .iter()
+        .enumerate()
+        .map(|(x, &height)| {
+            (0..height)
+                .filter(|&y| board.get_block_at(x, y).unwrap().is_empty())
+                .count()
+        })
+        .sum();
+
+    let aggregate_height: usize = heights.iter().sum();
+    let bumpiness: usize = heights
+        .windows(2)
+        .map(|pair| pair[0].abs_diff(pair[1]))
+        .sum();
+
+    HEIGHT_WEIGHT * aggregate_height as f32
+        + LINES_WEIGHT * lines as f32
+        + HOLES_WEIGHT * holes as f32
+        + BUMPINESS_WEIGHT * bumpiness as f32
+}
@@ -0,0 +1,380 @@
+use std::time::Duration;
+
+use super::board::{Board, BoardEvent};
+use super::game::{self, Game};
+use super::renderer::{
+    data::Instance,
+    particles::{Burst, Particles},
+    text::SOLID_CELL,
+    tiles,
+};
+use super::tetrimino::Color;
+
+const LOCK_FLASH_FRAMES: u32 = 8;
+const TRAIL_FRAMES: u32 = 10;
+// how long a clear flashes when there's no line clear delay to fill
+const CLEAR_FLASH_FRAMES: u32 = 10;
+const SHAKE_FRAMES: u32 = 12;
+
+// shake strengths in blocks
+const HARD_DROP_SHAKE: f32 = 0.08;
+const TETRIS_SHAKE: f32 = 0.3;
+
+enum Kind {
+    Lock {
+        cells: Vec<(i32, i32)>,
+    },
+    Trail {
+        cells: Vec<(i32, i32)>,
+        distance: u32,
+        color: Color,
+    },
+    Clear {
+        rows: Vec<(usize, Vec<Color>)>,
+        // the rows are still on the board and shrink away before it collapses
+        collapse: bool,
+    },
+}
+
+struct Effect {
+    kind: Kind,
+    age: u32,
+    length: u32,
+}
+
+impl Effect {
+    // 0 when the effect starts, approaching 1 as it ends
+    fn progress(&self) -> f32 {
+        self.age as f32 / self.length as f32
+    }
+}
+
+// purely cosmetic effects for one board, fed by the events of each frame
+pub struct Animator {
+    effects: Vec<Effect>,
+    particles: Particles,
+    shake: f32,
+    shake_age: u32,
+    // the clock of the last frame read, a frame can be looked at more than once
+    seen: Option<Duration>,
+}
+
+impl Animator {
+    pub fn new(particles: f32) -> Self {
+        Self {
+            effects: Vec::new(),
+            particles: Particles::new(particles),
+            shake: 0.0,
+            shake_age: 0,
+            seen: None,
+        }
+    }
+
+    pub fn update(&mut self, game: &Game) {
+        self.particles.tick();
+        for effect in self.effects.iter_mut() {
+            effect.age += 1;
+        }
+        self.effects.retain(|effect| effect.age < effect.length);
+        self.shake_age = (self.shake_age + 1).min(SHAKE_FRAMES);
+
+        if self.seen == Some(game.clock) {
+            return;
+        }
+        self.seen = Some(game.clock);
+
+        self.emit_particles(game);
+        for event in game.events() {
+            let (kind, length) = match event {
+                BoardEvent::Locked { cells, .. } => (
+                    Kind::Lock {
+                        cells: cells.clone(),
+                    },
+                    LOCK_FLASH_FRAMES,
+                ),
+                BoardEvent::HardDropped {
+                    cells,
+                    distance,
+                    color,
+                } => {
+                    self.start_shake(HARD_DROP_SHAKE);
+                    (
+                        Kind::Trail {
+                            cells: cells.clone(),
+                            distance: *distance,
+                            color: *color,
+                        },
+                        TRAIL_FRAMES,
+                    )
+                }
+                BoardEvent::Cleared { rows } => {
+                    if rows.len() >= 4 {
+                        self.start_shake(TETRIS_SHAKE);
+                    }
+                    let collapse = !game.board.clearing().is_empty() && game.line_clear_delay > 0;
+                    let length = if collapse {
+                        game.line_clear_delay
+                    } else {
+                        CLEAR_FLASH_FRAMES
+                    };
+                    (
+                        Kind::Clear {
+                            rows: rows.clone(),
+                            collapse,
+                        },
+                        length,
+                    )
+                }
+                BoardEvent::GarbageRose { .. } | BoardEvent::ToppedOut => continue,
+            };
+            if length > 0 {
+                self.effects.push(Effect {
+                    kind,
+                    age: 0,
+                    length,
+                });
+            }
+        }
+    }
+
+    // seeded from the frame and the piece count, so a replay throws the same particles
+    fn emit_particles(&mut self, game: &Game) {
+        let width = game.board.width() as f32;
+        let frame = game::frames(game.clock) as u64;
+        let seed = |kind: u64, index: u64| {
+            (frame << 24) ^ ((game.pieces as u64) << 8) ^ (kind << 4) ^ index
+        };
+
+        for event in game.events() {
+            match event {
+                BoardEvent::Cleared { rows } => {
+                    for (i, (row, colors)) in rows.iter().enumerate() {
+                        self.particles.emit(&Burst {
+                            origin: [width / 2.0, *row as f32 + 0.5],
+                            spread: [width, 1.0],
+                            count: 12,
+                            speed: 0.12,
+                            tints: colors.iter().map(|&color| tint(color)).collect(),
+                            seed: seed(0, i as u64),
+                        });
+                    }
+                }
+                BoardEvent::Locked {
+                    tspin,
+                    perfect_clear,
+                    cells,
+                    ..
+                } => {
+                    if *tspin && !cells.is_empty() {
+                        let count = cells.len() as f32;
+                        let x = cells.iter().map(|&(x, _)| x as f32).sum::<f32>() / count;
+                        let y = cells.iter().map(|&(_, y)| y as f32).sum::<f32>() / count;
+                        self.particles.emit(&Burst {
+                            origin: [x + 0.5, y + 0.5],
+                            spread: [3.0, 2.0],
+                            count: 30,
+                            speed: 0.2,
+                            tints: vec![tint(Color::Purple), [1.0, 0.85, 0.3]],
+                            seed: seed(1, 0),
+                        });
+                    }
+                    if *perfect_clear {
+                        self.particles.emit(&Burst {
+                            origin: [width / 2.0, 2.0],
+                            spread: [width, 4.0],
+                            count: 80,
+                            speed: 0.3,
+                            tints: [
+                                Color::Red,
+                                Color::Orange,
+                                Color::Yellow,
+                                Color::Green,
+                                Color::LightBlue,
+                                Color::Blue,
+                                Color::Purple,
+                            ]
+                            .into_iter()
+                            .map(tint)
+                            .collect(),
+                            seed: seed(2, 0),
+                        });
+                    }
+                }
+                BoardEvent::GarbageRose { lines } => {
+                    self.particles.emit(&Burst {
+                        origin: [width / 2.0, *lines as f32 / 2.0],
+                        spread: [width, *lines as f32],
+                        count: 6 * *lines as usize,
+                        speed: 0.08,
+                        tints: vec![tint(Color::Garbage)],
+                        seed: seed(3, 0),
+                    });
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn start_shake(&mut self, strength: f32) {
+        if self.shake_age < SHAKE_FRAMES && self.shake > strength {
+            return;
+        }
+        self.shake = strength;
+        self.shake_age = 0;
+    }
+
+    // how far the whole board is knocked off its place this frame
+    pub fn shake(&self, board: &Board) -> (f32, f32) {
+        if self.shake_age >= SHAKE_FRAMES {
+            return (0.0, 0.0);
+        }
+        let age = self.shake_age as f32;
+        let amplitude = self.shake * board.block_size() * (1.0 - age / SHAKE_FRAMES as f32);
+        (
+            amplitude * (age * 1.9).sin(),
+            -amplitude * (age * 2.3).cos().abs(),
+        )
+    }
+
+    // drawn from the tile atlas, right after the board
+    pub fn tiles(&self, board: &Board) -> Vec<Instance> {
+        let block_size = board.block_size();
+        let (left, bottom) = board.origin();
+        let visible = board.visible_height() as i32;
+        let mut instances = Vec::new();
+
+        for effect in &self.effects {
+            let fade = 1.0 - effect.progress();
+            match &effect.kind {
+                Kind::Clear {
+                    rows,
+                    collapse: true,
+                } => {
+                    let height = block_size * fade;
+                    for (row, colors) in rows {
+                        if *row as i32 >= visible {
+                            continue;
+                        }
+                        let y = bottom + (*row as f32 + 0.5) * block_size - height / 2.0;
+                        for (x, &ty) in colors.iter().enumerate() {
+                            instances.push(Instance {
+                                position: [left + x as f32 * block_size, y],
+                                size: [block_size, height],
+                                cell: tiles::cell(ty),
+                                tint: [1.0, 1.0, 1.0],
+                                alpha: 1.0,
+                                pattern: tiles::pattern(tiles::cell(ty)),
+                            });
+                        }
+                    }
+                }
+                Kind::Trail {
+                    cells,
+                    distance,
+                    color,
+                } => {
+                    // one streak above the highest block of each column
+                    for &(x, y) in cells {
+                        if cells
+                            .iter()
+                            .any(|&(other_x, other_y)| other_x == x && other_y > y)
+                        {
+                            continue;
+                        }
+                        let top = (y + 1 + *distance as i32).min(visible);
+                        if top <= y + 1 {
+                            continue;
+                        }
+                        instances.push(Instance {
+                            position: [
+                                left + (x as f32 + 0.2) * block_size,
+                                bottom + (y + 1) as f32 * block_size,
+                            ],
+                            size: [block_size * 0.6, (top - y - 1) as f32 * block_size],
+                            cell: tiles::cell(*color),
+                            tint: [1.0, 1.0, 1.0],
+                            alpha: 0.4 * fade,
+                            pattern: 0,
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        instances
+    }
+
+    // white flashes and particles drawn with the overlay
+    pub fn overlay(&self, board: &Board) -> Vec<Instance> {
+        let block_size = board.block_size();
+        let (left, bottom) = board.origin();
+        let visible = board.visible_height() as i32;
+        let mut instances = self.particles.instances(board.origin(), block_size);
+
+        for effect in &self.effects {
+            let fade = 1.0 - effect.progress();
+            match &effect.kind {
+                Kind::Lock { cells } => {
+                    for &(x, y) in cells {
+                        if y < visible {
+                            instances.push(flash(
+                                left + x as f32 * block_size,
+                                bottom + y as f32 * block_size,
+                                [block_size, block_size],
+                                0.6 * fade,
+                            ));
+                        }
+                    }
+                }
+                Kind::Clear { rows, collapse } => {
+                    let height = if *collapse {
+                        block_size * fade
+                    } else {
+                        block_size
+                    };
+                    for (row, colors) in rows {
+                        if *row as i32 >= visible {
+                            continue;
+                        }
+                        instances.push(flash(
+                            left,
+                            bottom + (*row as f32 + 0.5) * block_size - height / 2.0,
+                            [colors.len() as f32 * block_size, height],
+                            0.8 * fade,
+                        ));
+                    }
+                }
+                Kind::Trail { .. } => (),
+            }
+        }
+
+        instances
+    }
+}
+
+fn flash(x: f32, y: f32, size: [f32; 2], alpha: f32) -> Instance {
+    Instance {
+        position: [x, y],
+        size,
+        cell: SOLID_CELL,
+        tint: [1.0, 1.0, 1.0],
+        alpha,
+        pattern: 0,
+    }
+}
+
+// roughly the colors of the tile atlas
+fn tint(color: Color) -> [f32; 3] {
+    match color {
+        Color::Blue => [0.35, 0.45, 0.95],
+        Color::Red => [0.95, 0.45, 0.45],
+        Color::Green => [0.45, 0.85, 0.45],
+        Color::Orange => [0.98, 0.7, 0.35],
+        Color::Purple => [0.75, 0.5, 0.9],
+        Color::Yellow => [0.98, 0.9, 0.45],
+        Color::LightBlue => [0.5, 0.85, 0.98],
+        Color::Garbage => [0.6, 0.6, 0.6],
+        _ => [1.0, 1.0, 1.0],
+    }
+}
@@ -0,0 +1,500 @@
+use super::input::Input;
+use super::mode::{self, Mode};
+use super::options::Options;
+use super::palette::Palette;
+use super::renderer::{data::Instance, text};
+use super::server::MAX_PLAYERS;
+use super::session::Session;
+
+// world units in the solo window, see `App::text_scale`
+const HEADING_SIZE: f32 = 0.09;
+const TEXT_SIZE: f32 = 0.04;
+const HEADING_TOP: f32 = 0.45;
+const ITEMS_TOP: f32 = 0.22;
+const LINE_SPACING: f32 = 1.8;
+const SELECTED_COLOR: [f32; 3] = [1.0, 0.8, 0.2];
+const ITEM_COLOR: [f32; 3] = [0.6, 0.6, 0.65];
+// dims the game under the pause and results menus
+const SHADE_ALPHA: f32 = 0.8;
+const PANEL_ALPHA: f32 = 0.9;
+const PANEL_MARGIN: f32 = 0.05;
+// players in a battle unless the command line asked for a bigger one
+const DEFAULT_BATTLE_PLAYERS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Screen {
+    Title,
+    ModeSelect,
+    Settings,
+    Playing,
+    Paused,
+    Results,
+}
+
+// menus are driven by these rather than raw keys, so any frontend can map its own
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Confirm,
+    Back,
+}
+
+// what the frontend has to do after a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    // the palette or patterns changed, the skin needs loading again
+    Restyle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Item {
+    Play,
+    Settings,
+    Quit,
+    Marathon,
+    Ultra,
+    Cheese,
+    Versus,
+    Battle,
+    Free,
+    Level,
+    Players,
+    Grid,
+    Animations,
+    Palette,
+    Patterns,
+    Resume,
+    Restart,
+    ToTitle,
+    Back,
+}
+
+// the screens around the games, and the game being played if there is one
+pub struct App {
+    screen: Screen,
+    selected: usize,
+    options: Options,
+    session: Option<Session>,
+    // settings the mode select turns into a `Mode`
+    start_level: u32,
+    battle_players: usize,
+}
+
+impl App {
+    // straight into `session` when the command line already said what to play
+    pub fn new(options: Options, session: Option<Session>) -> Self {
+        let start_level = match options.mode {
+            Mode::Marathon { start_level, .. } => start_level,
+            _ => 1,
+        };
+        let battle_players = if options.players > 2 {
+            options.players
+        } else {
+            DEFAULT_BATTLE_PLAYERS
+        };
+        Self {
+            screen: if session.is_some() {
+                Screen::Playing
+            } else {
+                Screen::Title
+            },
+            selected: 0,
+            options,
+            session,
+            start_level,
+            battle_players,
+        }
+    }
+
+    pub fn screen(&self) -> Screen {
+        self.screen
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    // boards on screen, the window is sized for them
+    pub fn player_count(&self) -> usize {
+        self.session
+            .as_ref()
+            .map_or(1, |session| session.players.len())
+    }
+
+    // only reaches the game while it's being played
+    pub fn press(&mut self, input: Input) {
+        if let (Screen::Playing, Some(session)) = (self.screen, &mut self.session) {
+            session.press(input);
+        }
+    }
+
+    pub fn tick(&mut self) {
+        let Some(session) = &mut self.session else {
+            return;
+        };
+        // an online match carries on under the pause menu, the others can't be stopped
+        let running = match self.screen {
+            Screen::Paused => session.connection.is_some(),
+            _ => true,
+        };
+        if running {
+            session.tick();
+        }
+        if self.screen == Screen::Playing && session.is_over() {
+            self.show(Screen::Results);
+        }
+    }
+
+    pub fn process_events(&mut self) {
+        if let Some(session) = &mut self.session {
+            session.process_events();
+        }
+    }
+
+    pub fn status(&self) -> Option<String> {
+        self.session.as_ref().map(Session::status)
+    }
+
+    // whatever happens the recording is finished and the other players told
+    pub fn quit(&mut self) {
+        if let Some(mut session) = self.session.take() {
+            session.stop_recording();
+            session.disconnect();
+        }
+    }
+
+    pub fn key(&mut self, key: Key) -> Option<Action> {
+        if self.screen == Screen::Playing {
+            if key == Key::Back {
+                self.show(Screen::Paused);
+            }
+            return None;
+        }
+
+        let items = self.items();
+        match key {
+            Key::Up => self.selected = (self.selected + items.len() - 1) % items.len(),
+            Key::Down => self.selected = (self.selected + 1) % items.len(),
+            Key::Left => return self.adjust(items[self.selected], -1),
+            Key::Right => return self.adjust(items[self.selected], 1),
+            Key::Confirm => return self.choose(items[self.selected]),
+            Key::Back => {
+                let back = match self.screen {
+                    Screen::Title => Item::Quit,
+                    Screen::Paused => Item::Resume,
+                    Screen::Results => Item::ToTitle,
+                    _ => Item::Back,
+                };
+                return self.choose(back);
+            }
+        }
+        None
+    }
+
+    fn show(&mut self, screen: Screen) {
+        self.screen = screen;
+        self.selected = 0;
+    }
+
+    fn items(&self) -> Vec<Item> {
+        match self.screen {
+            Screen::Title => vec![Item::Play, Item::Settings, Item::Quit],
+            Screen::ModeSelect => vec![
+                Item::Marathon,
+                Item::Ultra,
+                Item::Cheese,
+                Item::Versus,
+                Item::Battle,
+                Item::Free,
+                Item::Back,
+            ],
+            Screen::Settings => vec![
+                Item::Level,
+                Item::Players,
+                Item::Grid,
+                Item::Animations,
+                Item::Palette,
+                Item::Patterns,
+                Item::Back,
+            ],
+            Screen::Playing => Vec::new(),
+            Screen::Paused if self.can_restart() => {
+                vec![Item::Resume, Item::Restart, Item::ToTitle, Item::Quit]
+            }
+            Screen::Paused => vec![Item::Resume, Item::ToTitle, Item::Quit],
+            Screen::Results if self.can_restart() => {
+                vec![Item::Restart, Item::ToTitle, Item::Quit]
+            }
+            Screen::Results => vec![Item::ToTitle, Item::Quit],
+        }
+    }
+
+    // online games and spectating can't just start over
+    fn can_restart(&self) -> bool {
+        self.options.net.is_none() && self.options.watch.is_none()
+    }
+
+    fn choose(&mut self, item: Item) -> Option<Action> {
+        match item {
+            Item::Play => self.show(Screen::ModeSelect),
+            Item::Settings => self.show(Screen::Settings),
+            Item::Quit => {
+                self.quit();
+                return Some(Action::Quit);
+            }
+            Item::Marathon => self.start(
+                Mode::Marathon {
+                    start_level: self.start_level,
+                    endless: false,
+                },
+                1,
+            ),
+            Item::Ultra => self.start(
+                Mode::Ultra {
+                    time_limit: mode::DEFAULT_ULTRA_TIME,
+                },
+                1,
+            ),
+            Item::Cheese => self.start(
+                Mode::Cheese {
+                    lines: mode::DEFAULT_CHEESE_LINES,
+                    messiness: mode::DEFAULT_CHEESE_MESSINESS,
+                },
+                1,
+            ),
+            Item::Versus => self.start(self.versus(), 2),
+            Item::Battle => self.start(self.versus(), self.battle_players),
+            Item::Free => self.start(Mode::Free, 1),
+            // settings change with left and right, enter goes the same way as right
+            Item::Level
+            | Item::Players
+            | Item::Grid
+            | Item::Animations
+            | Item::Palette
+            | Item::Patterns => return self.adjust(item, 1),
+            Item::Resume => self.screen = Screen::Playing,
+            Item::Restart => {
+                self.quit();
+                self.start(self.options.mode, self.options.players);
+            }
+            Item::ToTitle => {
+                self.quit();
+                self.show(Screen::Title);
+            }
+            Item::Back => self.show(Screen::Title),
+        }
+        None
+    }
+
+    fn adjust(&mut self, item: Item, step: i32) -> Option<Action> {
+        match item {
+            Item::Level => {
+                let level = self.start_level as i32 + step;
+                self.start_level = level.clamp(1, mode::MARATHON_MAX_LEVEL as i32) as u32;
+            }
+            Item::Players => {
+                let players = self.battle_players as i32 + step;
+                self.battle_players = players.clamp(3, MAX_PLAYERS as i32) as usize;
+            }
+            Item::Grid => self.options.grid = !self.options.grid,
+            Item::Animations => self.options.animations = !self.options.animations,
+            Item::Palette => {
+                let count = Palette::ALL.len();
+                let current = Palette::ALL
+                    .iter()
+                    .position(|palette| *palette == self.options.palette)
+                    .unwrap_or(0);
+                let next = (current as i32 + step).rem_euclid(count as i32) as usize;
+                self.options.palette = Palette::ALL[next];
+                return Some(Action::Restyle);
+            }
+            Item::Patterns => {
+                self.options.patterns = !self.options.patterns;
+                return Some(Action::Restyle);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn versus(&self) -> Mode {
+        Mode::Versus {
+            garbage_delay: mode::DEFAULT_GARBAGE_DELAY,
+        }
+    }
+
+    // a new local game, staying on the menu if it can't be set up
+    fn start(&mut self, mode: Mode, players: usize) {
+        self.options.mode = mode;
+        self.options.players = players;
+        // games from the menu are local, whatever the command line connected to or recorded
+        self.options.net = None;
+        self.options.watch = None;
+        self.options.broadcast = None;
+        self.options.record = None;
+        match super::start_session(&self.options) {
+            Ok(session) => {
+                self.session = Some(session);
+                self.show(Screen::Playing);
+            }
+            Err(e) => eprintln!("Couldn't start the game: {:#}", e),
+        }
+    }
+
+    pub fn to_instances(&self) -> Vec<Instance> {
+        self.session
+            .as_ref()
+            .map_or_else(Vec::new, Session::to_instances)
+    }
+
+    // the game's own overlay, then the menu over it
+    pub fn overlay_instances(&self) -> Vec<Instance> {
+        let mut instances = self
+            .session
+            .as_ref()
+            .map_or_else(Vec::new, Session::overlay_instances);
+        if self.screen == Screen::Playing {
+            return instances;
+        }
+
+        let scale = self.text_scale();
+        let text_size = TEXT_SIZE * scale;
+        let mut menu = Vec::new();
+        let (heading, lines) = self.heading();
+        menu.extend(text::centered_text_instances(
+            &heading,
+            0.0,
+            HEADING_TOP * scale,
+            HEADING_SIZE * scale,
+        ));
+        let mut y = (HEADING_TOP - HEADING_SIZE * LINE_SPACING) * scale;
+        for line in lines {
+            menu.extend(text::centered_text_instances(&line, 0.0, y, text_size));
+            y -= text_size * LINE_SPACING;
+        }
+
+        let mut y = (ITEMS_TOP * scale).min(y - text_size);
+        for (i, item) in self.items().into_iter().enumerate() {
+            let label = self.label(item);
+            let (label, color) = if i == self.selected {
+                (format!("> {} <", label), SELECTED_COLOR)
+            } else {
+                (label, ITEM_COLOR)
+            };
+            for mut instance in text::centered_text_instances(&label, 0.0, y, text_size) {
+                instance.tint = color;
+                menu.push(instance);
+            }
+            y -= text_size * LINE_SPACING;
+        }
+
+        // the game dimmed, and a panel to keep the menu readable over the boards
+        if self.session.is_some() {
+            // far past the edges of any window
+            instances.push(shade([-5.0, -5.0], [10.0, 10.0], SHADE_ALPHA));
+            let (mut low, mut high) = ([f32::MAX; 2], [f32::MIN; 2]);
+            for instance in &menu {
+                for axis in 0..2 {
+                    low[axis] = low[axis].min(instance.position[axis]);
+                    high[axis] = high[axis].max(instance.position[axis] + instance.size[axis]);
+                }
+            }
+            let margin = PANEL_MARGIN * scale;
+            let position = [low[0] - margin, low[1] - margin];
+            let size = [
+                high[0] - low[0] + margin * 2.0,
+                high[1] - low[1] + margin * 2.0,
+            ];
+            instances.push(shade(position, size, PANEL_ALPHA));
+        }
+        instances.extend(menu);
+
+        instances
+    }
+
+    // the wider windows fit the same view width into more pixels, so text shrinks to match
+    fn text_scale(&self) -> f32 {
+        let solo = super::window_size(1).width;
+        (solo / super::window_size(self.player_count()).width) as f32
+    }
+
+    // the screen's title and anything to say under it
+    fn heading(&self) -> (String, Vec<String>) {
+        match self.screen {
+            Screen::Title => (
+                String::from("Tetris"),
+                vec![String::from("Arrows to choose, enter to pick")],
+            ),
+            Screen::ModeSelect => (String::from("Mode"), Vec::new()),
+            Screen::Settings => (
+                String::from("Settings"),
+                vec![String::from("Left and right to change")],
+            ),
+            Screen::Playing => (String::new(), Vec::new()),
+            Screen::Paused => (String::from("Paused"), Vec::new()),
+            Screen::Results => {
+                let Some(session) = &self.session else {
+                    return (String::from("Game Over"), Vec::new());
+                };
+                let heading = match session.winner {
+                    Some(winner) if session.is_versus() => {
+                        format!("{} wins", session.players[winner].name)
+                    }
+                    None if session.draw => String::from("Draw"),
+                    _ => String::from("Game Over"),
+                };
+                let lines = session.players[0]
+                    .game
+                    .hud()
+                    .into_iter()
+                    .filter(|(_, value)| !value.is_empty())
+                    .map(|(label, value)| format!("{} {}", label, value))
+                    .collect();
+                (heading, lines)
+            }
+        }
+    }
+
+    fn label(&self, item: Item) -> String {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        match item {
+            Item::Play => String::from("Play"),
+            Item::Settings => String::from("Settings"),
+            Item::Quit => String::from("Quit"),
+            Item::Marathon => String::from("Marathon"),
+            Item::Ultra => String::from("Ultra"),
+            Item::Cheese => String::from("Cheese race"),
+            Item::Versus => String::from("Versus AI"),
+            Item::Battle => format!("Battle of {}", self.battle_players),
+            Item::Free => String::from("Free play"),
+            Item::Level => format!("Marathon level {}", self.start_level),
+            Item::Players => format!("Battle players {}", self.battle_players),
+            Item::Grid => format!("Grid {}", on_off(self.options.grid)),
+            Item::Animations => format!("Animations {}", on_off(self.options.animations)),
+            Item::Palette => format!("Palette {}", self.options.palette.name()),
+            Item::Patterns => format!("Patterns {}", on_off(self.options.patterns)),
+            Item::Resume => String::from("Resume"),
+            Item::Restart => match self.screen {
+                Screen::Results => String::from("Play again"),
+                _ => String::from("Restart"),
+            },
+            Item::ToTitle => String::from("Quit to title"),
+            Item::Back => String::from("Back"),
+        }
+    }
+}
+
+// a black rectangle over whatever's behind
+fn shade(position: [f32; 2], size: [f32; 2], alpha: f32) -> Instance {
+    Instance {
+        position,
+        size,
+        tint: [0.0, 0.0, 0.0],
+        alpha,
+        ..Instance::tile(0.0, 0.0, 1.0, text::SOLID_CELL)
+    }
+}
@@ -0,0 +1,157 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::game::Game;
+
+// badges needed for each extra 25% of attack
+const BADGE_THRESHOLDS: [u32; 4] = [2, 6, 14, 30];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Targeting {
+    Random,
+    // everyone currently attacking you
+    Attackers,
+    // whoever is closest to topping out
+    KOs,
+    // whoever holds the most badges
+    Badges,
+}
+
+impl Targeting {
+    pub const ALL: [Targeting; 4] = [
+        Targeting::Random,
+        Targeting::Attackers,
+        Targeting::KOs,
+        Targeting::Badges,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Targeting::Random => "Random",
+            Targeting::Attackers => "Attackers",
+            Targeting::KOs => "KOs",
+            Targeting::Badges => "Badges",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|targeting| targeting.name().eq_ignore_ascii_case(name))
+    }
+}
+
+// who attacks whom in a match, cloned along with the games so rollback can rewind it
+#[derive(Clone)]
+pub struct Battle {
+    rng: StdRng,
+    targeting: Vec<Targeting>,
+    targets: Vec<Option<usize>>,
+    last_attacker: Vec<Option<usize>>,
+    knocked_out: Vec<bool>,
+    pub badges: Vec<u32>,
+}
+
+impl Battle {
+    pub fn new(players: usize, seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed.wrapping_add(2)),
+            targeting: vec![Targeting::Random; players],
+            targets: vec![None; players],
+            last_attacker: vec![None; players],
+            knocked_out: vec![false; players],
+            badges: vec![0; players],
+        }
+    }
+
+    pub fn targeting(&self, player: usize) -> Targeting {
+        self.targeting[player]
+    }
+
+    pub fn set_targeting(&mut self, player: usize, targeting: Targeting) {
+        self.targeting[player] = targeting;
+    }
+
+    pub fn target(&self, player: usize) -> Option<usize> {
+        self.targets[player]
+    }
+
+    // sends everyone's outgoing garbage in player order so every peer agrees
+    pub fn route_garbage(&mut self, games: &mut [&mut Game]) {
+        for attacker in 0..games.len() {
+            let attack = games[attacker].take_outgoing();
+            if attack == 0 {
+                continue;
+            }
+            let attack = attack + attack * badge_bonus(self.badges[attacker]) / 100;
+
+            for target in self.pick_targets(attacker, games) {
+                games[target].receive_garbage(attack);
+                self.last_attacker[target] = Some(attacker);
+            }
+        }
+
+        // whoever attacked a player last gets the KO, along with their badges
+        for (player, game) in games.iter().enumerate() {
+            if !game.over || self.knocked_out[player] {
+                continue;
+            }
+            self.knocked_out[player] = true;
+            if let Some(attacker) = self.last_attacker[player] {
+                self.badges[attacker] += 1 + self.badges[player];
+            }
+        }
+    }
+
+    fn pick_targets(&mut self, attacker: usize, games: &[&mut Game]) -> Vec<usize> {
+        let opponents: Vec<usize> = (0..games.len())
+            .filter(|&player| player != attacker && !games[player].over)
+            .collect();
+        if opponents.is_empty() {
+            self.targets[attacker] = None;
+            return Vec::new();
+        }
+
+        let targets = match self.targeting[attacker] {
+            Targeting::Random => Vec::new(),
+            Targeting::Attackers => opponents
+                .iter()
+                .copied()
+                .filter(|&player| self.targets[player] == Some(attacker))
+                .collect(),
+            Targeting::KOs => opponents
+                .iter()
+                .copied()
+                .max_by_key(|&player| danger(games[player]))
+                .into_iter()
+                .collect(),
+            Targeting::Badges => opponents
+                .iter()
+                .copied()
+                .filter(|&player| self.badges[player] > 0)
+                .max_by_key(|&player| self.badges[player])
+                .into_iter()
+                .collect(),
+        };
+
+        // nobody fits the strategy, so anyone will do
+        let targets = if targets.is_empty() {
+            vec![opponents[self.rng.gen_range(0, opponents.len())]]
+        } else {
+            targets
+        };
+        self.targets[attacker] = targets.first().copied();
+        targets
+    }
+}
+
+fn danger(game: &Game) -> u32 {
+    game.board.stack_height() as u32 + game.incoming.total()
+}
+
+fn badge_bonus(badges: u32) -> u32 {
+    BADGE_THRESHOLDS
+        .iter()
+        .filter(|&&threshold| badges >= threshold)
+        .count() as u32
+        * 25
+}
@@ -0,0 +1,9 @@
+use anyhow::Result;
+
+fn main() -> Result<()> {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| String::from("127.0.0.1:7878"));
+
+    real_tetris::server::run(&addr)
+}
@@ -1,6 +1,49 @@
-use super::renderer::data::Vertex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rand::{rngs::StdRng, SeedableRng};
+
 use super::tetrimino::{Color, Tetrimino};
 
+#[derive(Clone, Debug)]
+pub enum BoardEvent {
+    Locked {
+        lines: usize,
+        tspin: bool,
+        perfect_clear: bool,
+        // where the piece's blocks ended up
+        cells: Vec<(i32, i32)>,
+    },
+    // the full rows as they were just before being cleared
+    Cleared {
+        rows: Vec<(usize, Vec<Color>)>,
+    },
+    HardDropped {
+        cells: Vec<(i32, i32)>,
+        distance: u32,
+        color: Color,
+    },
+    // pushed by the game when incoming garbage rises, the board can't tell it from cheese
+    GarbageRose {
+        lines: u32,
+    },
+    ToppedOut,
+}
+
+// the playfield, the rest of the board is buffer zone pieces can still go into
+pub const VISIBLE_ROWS: usize = 20;
+// rows of the buffer zone shown faded above the playfield
+pub const BUFFER_HINT_ROWS: usize = 2;
+// how many upcoming pieces are shown
+pub const NEXT_PIECES: usize = 3;
+
+// no two boards share a revision unless one is a copy of the other, rolled back say
+static REVISIONS: AtomicU64 = AtomicU64::new(0);
+
+fn next_revision() -> u64 {
+    REVISIONS.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Clone)]
 pub struct Block {
     pub ty: Color,
@@ -9,52 +52,16 @@ impl Block {
     pub fn is_empty(&self) -> bool {
         matches!(self.ty, Color::Empty)
     }
-    pub fn to_vertices(&self, pos_x: f32, pos_y: f32, block_size: f32) -> Option<Vec<Vertex>> {
-        let min_uv: [f32; 2] = match self.ty {
-            Color::Blue => [0.0, 0.0],
-            Color::Red => [0.125, 0.0],
-            Color::Green => [0.25, 0.0],
-            Color::Orange => [0.375, 0.0],
-            Color::Purple => [0.5, 0.0],
-            Color::Yellow => [0.625, 0.0],
-            Color::LightBlue => [0.75, 0.0],
-            _ => [0.875, 0.0],
-        };
-
-        let min_pos = [pos_x, pos_y, 0.0];
-
-        Some(vec![
-            Vertex {
-                position: min_pos,
-                uv: min_uv,
-            },
-            Vertex {
-                position: [min_pos[0] + block_size, min_pos[1], min_pos[2]],
-                uv: [min_uv[0] + 0.125, min_uv[1]],
-            },
-            Vertex {
-                position: [min_pos[0] + block_size, min_pos[1] + block_size, min_pos[2]],
-                uv: [min_uv[0] + 0.125, min_uv[1] + 1.0],
-            },
-            Vertex {
-                position: min_pos,
-                uv: min_uv,
-            },
-            Vertex {
-                position: [min_pos[0], min_pos[1] + block_size, min_pos[2]],
-                uv: [min_uv[0], min_uv[1] + 1.0],
-            },
-            Vertex {
-                position: [min_pos[0] + block_size, min_pos[1] + block_size, min_pos[2]],
-                uv: [min_uv[0] + 0.125, min_uv[1] + 1.0],
-            },
-        ])
-    }
 }
 
+#[derive(Clone)]
 pub struct Board {
     width: usize,
     height: usize,
+    // rows above this are still played but only the first few are hinted at
+    visible_height: usize,
+    buffer_hint: usize,
+    grid: bool,
     block_size: f32,
     offset_x: f32,
     offset_y: f32,
@@ -64,6 +71,20 @@ pub struct Board {
     pub current_tetrimino_pos_x: i32,
     pub current_tetrimino_pos_y: i32,
     pub current_tetrimino_rotation: i8,
+
+    last_move_rotation: bool,
+    // when set, locking leaves full rows in place and no piece in play until the game says so
+    entry_delay: bool,
+    clearing: Vec<usize>,
+    events: Vec<BoardEvent>,
+    rng: StdRng,
+    // upcoming pieces, drawn from the rng ahead of time so the sequence is unchanged
+    next: VecDeque<Tetrimino>,
+    held: Option<Tetrimino>,
+    // one hold per piece, until the next one spawns
+    can_hold: bool,
+    // changes with anything that changes how the board looks
+    revision: u64,
 }
 
 impl Board {
@@ -77,6 +98,9 @@ impl Board {
         Self {
             width,
             height,
+            visible_height: VISIBLE_ROWS.min(height),
+            buffer_hint: BUFFER_HINT_ROWS,
+            grid: false,
             data,
             block_size,
             offset_x,
@@ -85,50 +109,100 @@ impl Board {
             current_tetrimino_pos_x: 0,
             current_tetrimino_pos_y: 0,
             current_tetrimino_rotation: 0,
+            last_move_rotation: false,
+            entry_delay: false,
+            clearing: Vec::new(),
+            events: Vec::new(),
+            rng: StdRng::from_entropy(),
+            next: VecDeque::new(),
+            held: None,
+            can_hold: true,
+            revision: next_revision(),
         }
     }
 
-    pub fn to_vertices(&self) -> Vec<Vertex> {
-        let mut all_verts = Vec::new();
+    // anything that changes how the board looks goes through here
+    fn touch(&mut self) {
+        self.revision = next_revision();
+    }
 
-        for i in 0..self.width * self.height {
-            let x = (i % self.width) as f32 * self.block_size;
-            let y = (i / self.width) as f32 * self.block_size;
-            let block = &self.data[i];
-            if let Some(verts) =
-                block.to_vertices(x - self.offset_x, y - self.offset_y, self.block_size)
-            {
-                all_verts.extend(&verts);
-            }
-        }
+    // tells whoever draws the board whether it's changed since they last did
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
 
-        if let Some(tetrimino) = &self.current_tetrimino {
-            let len = tetrimino.get_length();
-            for i in 0..len * len {
-                let x = (i % len) as f32 + self.current_tetrimino_pos_x as f32;
-                let y = (0 - (i / len) + len) as f32 + self.current_tetrimino_pos_y as f32;
-                let is_block = tetrimino.get_blocks(self.current_tetrimino_rotation)[i as usize];
-                if is_block == 1 {
-                    let block = Block {
-                        ty: tetrimino.get_color(),
-                    };
-                    let verts = block.to_vertices(
-                        x * self.block_size - self.offset_x,
-                        y * self.block_size - self.offset_y,
-                        self.block_size,
-                    );
-                    if let Some(verts) = verts {
-                        all_verts.extend(verts);
-                    }
-                }
-            }
-        }
+    // the blocks of the falling piece in board coordinates
+    pub fn current_cells(&self) -> Vec<(i32, i32)> {
+        let Some(tetrimino) = &self.current_tetrimino else {
+            return Vec::new();
+        };
+
+        let len = tetrimino.get_length();
+        let blocks = tetrimino.get_blocks(self.current_tetrimino_rotation);
+        (0..len * len)
+            .filter(|&i| blocks[i as usize] == 1)
+            .map(|i| {
+                (
+                    self.current_tetrimino_pos_x + i % len,
+                    self.current_tetrimino_pos_y + len - i / len,
+                )
+            })
+            .collect()
+    }
+
+    pub fn block_size(&self) -> f32 {
+        self.block_size
+    }
+
+    // bottom left corner of the board on screen
+    pub fn origin(&self) -> (f32, f32) {
+        (-self.offset_x, -self.offset_y)
+    }
+
+    pub fn set_visible_height(&mut self, rows: usize) {
+        self.touch();
+        self.visible_height = rows.min(self.height);
+    }
 
-        all_verts
+    pub fn visible_height(&self) -> usize {
+        self.visible_height
     }
 
-    pub fn num_vertices(&self) -> usize {
-        self.width * self.height * 6
+    pub fn buffer_hint(&self) -> usize {
+        self.buffer_hint
+    }
+
+    pub fn set_buffer_hint(&mut self, rows: usize) {
+        self.touch();
+        self.buffer_hint = rows;
+    }
+
+    pub fn grid(&self) -> bool {
+        self.grid
+    }
+
+    pub fn set_grid(&mut self, grid: bool) {
+        self.touch();
+        self.grid = grid;
+    }
+
+    pub fn set_entry_delay(&mut self, entry_delay: bool) {
+        self.touch();
+        self.entry_delay = entry_delay;
+    }
+
+    // full rows still on the board, waiting for `finish_clear`
+    pub fn clearing(&self) -> &[usize] {
+        &self.clearing
+    }
+
+    pub fn finish_clear(&mut self) {
+        self.touch();
+        let mut rows = std::mem::take(&mut self.clearing);
+        rows.sort();
+        for row in rows.into_iter().rev() {
+            self.delete_row(row);
+        }
     }
 
     pub fn get_block_at(&self, x: usize, y: usize) -> Option<&Block> {
@@ -176,6 +250,7 @@ impl Board {
         y: i32,
         tetrimino_rotation: i8,
     ) -> bool {
+        self.touch();
         let valid = self.tetrimino_valid(tetrimino, x, y, tetrimino_rotation);
 
         if valid {
@@ -205,31 +280,30 @@ impl Board {
         pos_y: i32,
         rotation: i8,
     ) {
+        self.touch();
         self.current_tetrimino = Some(*tetrimino);
         self.current_tetrimino_pos_x = pos_x;
         self.current_tetrimino_pos_y = pos_y;
         self.current_tetrimino_rotation = rotation;
+        self.last_move_rotation = false;
     }
 
     pub fn rotate_left(&mut self) -> bool {
+        self.touch();
         let mut next_rotation = self.current_tetrimino_rotation - 1;
         if next_rotation < 0 {
             next_rotation = 3;
         }
 
         let valid = self.current_tetrimino_valid(0, 0, next_rotation);
-
-        println!(
-            "{:?}, {:?}",
-            valid,
-            (self.current_tetrimino_rotation - 1) % 4
-        );
         if valid {
             self.current_tetrimino_rotation = next_rotation;
+            self.last_move_rotation = true;
         }
         valid
     }
     pub fn rotate_right(&mut self) -> bool {
+        self.touch();
         let mut next_rotation = self.current_tetrimino_rotation + 1;
         if next_rotation > 3 {
             next_rotation = 0;
@@ -237,12 +311,14 @@ impl Board {
 
         let valid = self.current_tetrimino_valid(0, 0, next_rotation);
         if valid {
-            self.current_tetrimino_rotation = next_rotation
+            self.current_tetrimino_rotation = next_rotation;
+            self.last_move_rotation = true;
         }
         valid
     }
 
     pub fn place_current_tetrimino(&mut self) -> bool {
+        self.touch();
         let placed = self.place_tetrimino(
             &self.current_tetrimino.unwrap(),
             self.current_tetrimino_pos_x,
@@ -253,12 +329,70 @@ impl Board {
             panic!("Tetrimino not placed!")
         }
 
-        self.current_tetrimino = Some(Tetrimino::random());
-        self.current_tetrimino_pos_x = 3;
-        self.current_tetrimino_pos_y = 15;
-
         placed
     }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.next.clear();
+        self.fill_queue();
+    }
+
+    fn fill_queue(&mut self) {
+        while self.next.len() < NEXT_PIECES {
+            self.next.push_back(Tetrimino::random(&mut self.rng));
+        }
+    }
+
+    fn take_next(&mut self) -> Tetrimino {
+        self.fill_queue();
+        let tetrimino = self.next.pop_front().unwrap();
+        self.fill_queue();
+        tetrimino
+    }
+
+    pub fn next_pieces(&self) -> impl Iterator<Item = &Tetrimino> {
+        self.next.iter()
+    }
+
+    pub fn held(&self) -> Option<Tetrimino> {
+        self.held
+    }
+
+    pub fn spawn_tetrimino(&mut self) -> bool {
+        self.touch();
+        let tetrimino = self.take_next();
+        self.can_hold = true;
+        self.enter(tetrimino)
+    }
+
+    // swaps the piece in play with the held one, or the next one the first time
+    pub fn hold(&mut self) -> bool {
+        let Some(current) = self.current_tetrimino else {
+            return false;
+        };
+        if !self.can_hold {
+            return false;
+        }
+
+        self.touch();
+        let tetrimino = match self.held.replace(current) {
+            Some(held) => held,
+            None => self.take_next(),
+        };
+        self.can_hold = false;
+        self.enter(tetrimino)
+    }
+
+    fn enter(&mut self, tetrimino: Tetrimino) -> bool {
+        self.set_current_tetrimino(&tetrimino, 3, 15, 0);
+
+        let valid = self.current_tetrimino_valid(0, 0, 0);
+        if !valid {
+            self.events.push(BoardEvent::ToppedOut);
+        }
+        valid
+    }
     pub fn current_tetrimino_valid(&self, off_x: i32, off_y: i32, rot: i8) -> bool {
         self.current_tetrimino.is_some()
             && self.tetrimino_valid(
@@ -270,31 +404,122 @@ impl Board {
     }
 
     pub fn move_left(&mut self) -> bool {
+        self.touch();
         let valid = self.current_tetrimino_valid(-1, 0, self.current_tetrimino_rotation);
         if valid {
             self.current_tetrimino_pos_x -= 1;
+            self.last_move_rotation = false;
         }
         valid
     }
     pub fn move_right(&mut self) -> bool {
+        self.touch();
         let valid = self.current_tetrimino_valid(1, 0, self.current_tetrimino_rotation);
         if valid {
             self.current_tetrimino_pos_x += 1;
+            self.last_move_rotation = false;
         }
         valid
     }
     pub fn move_down(&mut self) -> bool {
+        self.touch();
+        if self.current_tetrimino.is_none() {
+            return false;
+        }
+
         let valid = self.current_tetrimino_valid(0, -1, self.current_tetrimino_rotation);
         if valid {
             self.current_tetrimino_pos_y -= 1;
+            self.last_move_rotation = false;
         } else {
-            self.place_current_tetrimino();
-            self.check_and_delete_rows();
+            self.lock_current_tetrimino();
         }
 
         valid
     }
 
+    pub fn hard_drop(&mut self) {
+        self.touch();
+        let Some(tetrimino) = self.current_tetrimino else {
+            return;
+        };
+
+        let mut distance = 0;
+        while self.current_tetrimino_valid(0, -1, self.current_tetrimino_rotation) {
+            self.move_down();
+            distance += 1;
+        }
+        self.events.push(BoardEvent::HardDropped {
+            cells: self.current_cells(),
+            distance,
+            color: tetrimino.get_color(),
+        });
+        self.move_down();
+    }
+
+    fn lock_current_tetrimino(&mut self) {
+        self.touch();
+        let tspin = self.is_tspin();
+        let cells = self.current_cells();
+        self.place_current_tetrimino();
+
+        let full_rows = self.get_full_rows();
+        let lines = full_rows.len();
+        let perfect_clear = lines > 0
+            && (0..self.height)
+                .filter(|row| !full_rows.contains(row))
+                .all(|row| self.get_row(row).iter().all(Block::is_empty));
+        self.events.push(BoardEvent::Locked {
+            lines,
+            tspin,
+            perfect_clear,
+            cells,
+        });
+        if lines > 0 {
+            let rows = full_rows
+                .iter()
+                .map(|&row| {
+                    (
+                        row,
+                        self.get_row(row).iter().map(|block| block.ty).collect(),
+                    )
+                })
+                .collect();
+            self.events.push(BoardEvent::Cleared { rows });
+        }
+
+        if self.entry_delay {
+            self.clearing = full_rows;
+            self.current_tetrimino = None;
+        } else {
+            self.check_and_delete_rows();
+            self.spawn_tetrimino();
+        }
+    }
+
+    // 3-corner rule: a T that last moved by rotating with 3 of its 4 diagonal corners filled
+    fn is_tspin(&self) -> bool {
+        if !matches!(self.current_tetrimino, Some(Tetrimino::T)) || !self.last_move_rotation {
+            return false;
+        }
+
+        let center_x = self.current_tetrimino_pos_x + 1;
+        let center_y = self.current_tetrimino_pos_y + 2;
+        [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+            .iter()
+            .filter(|(off_x, off_y)| {
+                let x = center_x + off_x;
+                let y = center_y + off_y;
+                x < 0
+                    || y < 0
+                    || self
+                        .get_block_at(x as usize, y as usize)
+                        .is_none_or(|block| !block.is_empty())
+            })
+            .count()
+            >= 3
+    }
+
     pub fn get_row(&self, row: usize) -> &[Block] {
         if row >= self.height {
             panic!("Row is greater than height.");
@@ -305,6 +530,7 @@ impl Board {
         row.iter().all(|block| !block.is_empty())
     }
     pub fn get_full_rows(&mut self) -> Vec<usize> {
+        self.touch();
         let mut full_rows = Vec::new();
         for i in 0..self.height {
             let row = self.get_row(i);
@@ -317,6 +543,7 @@ impl Board {
     }
 
     pub fn delete_row(&mut self, row: usize) {
+        self.touch();
         self.data
             .drain((row * self.width)..(row * self.width + self.width));
 
@@ -328,23 +555,166 @@ impl Board {
         self.data.append(&mut new_data);
     }
 
-    pub fn check_and_delete_rows(&mut self) {
+    pub fn insert_row_from_bottom(&mut self, row: Vec<Block>) {
+        self.touch();
+        if row.len() != self.width {
+            panic!("Row length doesn't match board width.");
+        }
+
+        let overflow = !self.get_row(self.height - 1).iter().all(Block::is_empty);
+
+        self.data.truncate((self.height - 1) * self.width);
+        self.data.splice(0..0, row);
+        for row in self.clearing.iter_mut() {
+            *row += 1;
+        }
+
+        if overflow {
+            self.events.push(BoardEvent::ToppedOut);
+        }
+
+        // push the falling piece up instead of letting the new row overlap it
+        if self.current_tetrimino.is_some()
+            && !self.current_tetrimino_valid(0, 0, self.current_tetrimino_rotation)
+        {
+            self.current_tetrimino_pos_y += 1;
+        }
+    }
+
+    pub fn insert_garbage_row(&mut self, hole: usize) {
+        self.touch();
+        let row = (0..self.width)
+            .map(|x| Block {
+                ty: if x == hole {
+                    Color::Empty
+                } else {
+                    Color::Garbage
+                },
+            })
+            .collect();
+        self.insert_row_from_bottom(row);
+    }
+
+    pub fn stack_height(&self) -> usize {
+        (0..self.height)
+            .rev()
+            .find(|&row| !self.get_row(row).iter().all(Block::is_empty))
+            .map_or(0, |row| row + 1)
+    }
+
+    pub fn count_garbage_rows(&self) -> usize {
+        (0..self.height)
+            .filter(|&row| {
+                self.get_row(row)
+                    .iter()
+                    .any(|block| matches!(block.ty, Color::Garbage))
+            })
+            .count()
+    }
+
+    // the whole field row by row from the bottom, with the falling piece drawn in
+    pub fn snapshot(&self) -> Vec<Color> {
+        let mut cells: Vec<Color> = self.data.iter().map(|block| block.ty).collect();
+
+        if let Some(tetrimino) = &self.current_tetrimino {
+            for (x, y) in self.current_cells() {
+                if x >= 0 && y >= 0 {
+                    let (x, y) = (x as usize, y as usize);
+                    if x < self.width && y < self.height {
+                        cells[y * self.width + x] = tetrimino.get_color();
+                    }
+                }
+            }
+        }
+
+        cells
+    }
+
+    pub fn load_snapshot(&mut self, cells: &[Color]) {
+        self.touch();
+        if cells.len() != self.data.len() {
+            panic!("Snapshot size doesn't match the board.");
+        }
+
+        for (block, &ty) in self.data.iter_mut().zip(cells) {
+            block.ty = ty;
+        }
+        self.current_tetrimino = None;
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn check_and_delete_rows(&mut self) -> usize {
+        self.touch();
         let mut full_rows = self.get_full_rows();
         full_rows.sort();
         full_rows.reverse();
 
+        let cleared = full_rows.len();
         for full_row in full_rows {
             self.delete_row(full_row);
         }
+        cleared
     }
 
-    pub fn update(&mut self) {
-        let start = std::time::Instant::now();
-        self.move_down();
-        let end = std::time::Instant::now();
+    pub fn take_events(&mut self) -> Vec<BoardEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(board: &Board, y: usize) -> String {
+        board
+            .get_row(y)
+            .iter()
+            .map(|block| block.ty.to_char())
+            .collect()
+    }
+
+    #[test]
+    fn garbage_rows_push_the_stack_up() {
+        let mut board = Board::new(4, 6, 1.0, 0.0, 0.0);
+        board.insert_garbage_row(1);
+        board.insert_garbage_row(3);
+        assert_eq!(row(&board, 0), "GGG.");
+        assert_eq!(row(&board, 1), "G.GG");
+        assert_eq!(board.count_garbage_rows(), 2);
+        assert!(board.take_events().is_empty());
+    }
+
+    #[test]
+    fn garbage_past_the_top_tops_out() {
+        let mut board = Board::new(4, 3, 1.0, 0.0, 0.0);
+        for _ in 0..3 {
+            board.insert_garbage_row(0);
+        }
+        assert!(board.take_events().is_empty());
+
+        board.insert_garbage_row(0);
+        assert!(matches!(
+            board.take_events().as_slice(),
+            [BoardEvent::ToppedOut]
+        ));
+    }
 
-        let dur = end - start;
+    #[test]
+    fn garbage_pushes_the_falling_piece_up() {
+        let mut board = Board::new(10, 40, 1.0, 0.0, 0.0);
+        // upside down, resting on the floor
+        board.set_current_tetrimino(&Tetrimino::T, 3, -1, 2);
+        assert!(board.current_cells().iter().any(|&(_, y)| y == 0));
 
-        println!("{:?}", dur.as_micros());
+        board.insert_garbage_row(0);
+        assert!(board.current_cells().iter().all(|&(_, y)| y >= 1));
+        assert!(board.current_tetrimino_valid(0, 0, 2));
     }
 }
@@ -0,0 +1,118 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Result};
+use winit::dpi::PhysicalSize;
+
+use super::board::Board;
+use super::fumen;
+use super::renderer::{tiles, ImageRenderer, Renderer, SoftwareRenderer};
+use super::skin::Skin;
+use super::tetrimino::Color;
+
+const PIXELS_PER_BLOCK: u32 = 32;
+const TILE_SIZE: f32 = 0.1;
+// rows shown even when the stack is lower
+const MIN_ROWS: usize = 20;
+
+// a saved board is one line per row from the top down, with the same letters as the
+// network protocol, or fumen data either inline or in the file
+pub fn load_board(input: &str) -> Result<Vec<Vec<Color>>> {
+    if fumen::is_fumen(input) {
+        return fumen::decode(input);
+    }
+
+    let text = fs::read_to_string(input)?;
+    if fumen::is_fumen(&text) {
+        return fumen::decode(&text);
+    }
+
+    let mut rows = text
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.chars()
+                .map(|c| Color::from_char(c).ok_or_else(|| anyhow!("Bad cell: {}", c)))
+                .collect::<Result<Vec<_>>>()
+        })
+        .collect::<Result<Vec<_>>>()?;
+    rows.reverse();
+    Ok(rows)
+}
+
+// wgpu when there's an adapter for it, otherwise the CPU, or the CPU straight away
+pub async fn image_renderer(
+    size: PhysicalSize<u32>,
+    view: [f32; 2],
+    skin: &Skin,
+    cpu: bool,
+) -> Box<dyn ImageRenderer> {
+    if !cpu {
+        match Renderer::headless(size, view, skin).await {
+            Ok(renderer) => return Box::new(renderer),
+            Err(e) => eprintln!("{:#}, drawing on the CPU instead", e),
+        }
+    }
+    Box::new(SoftwareRenderer::new(size, view, skin))
+}
+
+// draws just the board, without a window, so it works on machines with no GPU too
+pub async fn render_board(
+    input: &str,
+    output: &Path,
+    skin: &Skin,
+    grid: bool,
+    cpu: bool,
+) -> Result<()> {
+    let rows = load_board(input)?;
+    let (width, height) = (fumen::WIDTH, 40);
+    if rows.len() > height || rows.iter().any(|row| row.len() > width) {
+        bail!("Boards are at most {} wide and {} tall", width, height);
+    }
+
+    let visible = rows.len().max(MIN_ROWS);
+    let mut board = Board::new(
+        width,
+        height,
+        TILE_SIZE,
+        width as f32 * TILE_SIZE / 2.0,
+        visible as f32 * TILE_SIZE / 2.0,
+    );
+    board.set_visible_height(visible);
+    // the image is cut off right at the top row
+    board.set_buffer_hint(0);
+    board.set_grid(grid);
+
+    let mut cells = vec![Color::Empty; width * height];
+    for (y, row) in rows.iter().enumerate() {
+        cells[y * width..y * width + row.len()].copy_from_slice(row);
+    }
+    board.load_snapshot(&cells);
+
+    // half a block of room all round for the frame
+    let view = [
+        (width + 1) as f32 * TILE_SIZE,
+        (visible + 1) as f32 * TILE_SIZE,
+    ];
+    let size = PhysicalSize::new(
+        (width as u32 + 1) * PIXELS_PER_BLOCK,
+        (visible as u32 + 1) * PIXELS_PER_BLOCK,
+    );
+    image_renderer(size, view, skin, cpu)
+        .await
+        .render_to_image(&tiles::board_instances(&board), &[])?
+        .save(output)?;
+    Ok(())
+}
+
+// a new file in the working directory each time
+pub fn screenshot_path() -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis());
+    PathBuf::from(format!("screenshot-{}.png", millis))
+}
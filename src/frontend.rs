@@ -0,0 +1,163 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use super::game;
+use super::input::Input;
+use super::renderer::ImageRenderer;
+use super::session::Session;
+
+// frames the game may fall behind before it stops catching up, after a suspend say
+const MAX_LAG: u32 = 10;
+// how often the image frontend rewrites its file
+const IMAGE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrontendKind {
+    Window,
+    Terminal,
+    // the current frame saved to a file now and then
+    Image,
+    Null,
+}
+
+impl FrontendKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "window" => Some(Self::Window),
+            "terminal" => Some(Self::Terminal),
+            "image" => Some(Self::Image),
+            "null" => Some(Self::Null),
+            _ => None,
+        }
+    }
+}
+
+pub enum Command {
+    Press(Input),
+    // stops a local game until it comes again, online matches carry on
+    Pause,
+    Quit,
+}
+
+// shows the session and hears from the player, picked at startup
+pub trait Frontend {
+    // the session is only looked at here, every change goes through `Session::press`
+    fn draw(&mut self, session: &Session) -> Result<()>;
+
+    // waits up to `timeout` for the player, frontends without any input just wait
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Command>> {
+        std::thread::sleep(timeout);
+        Ok(None)
+    }
+
+    // told whenever the game is paused or resumed, for frontends that show it
+    fn set_paused(&mut self, _paused: bool) {}
+}
+
+// steps the session at the game's own rate, for frontends without an event loop of their own
+pub fn run(session: &mut Session, frontend: &mut dyn Frontend) -> Result<()> {
+    let mut next_frame = Instant::now();
+    let mut paused = false;
+
+    loop {
+        // input until the next frame is due
+        loop {
+            let timeout = next_frame.saturating_duration_since(Instant::now());
+            match frontend.poll(timeout)? {
+                Some(Command::Press(input)) if !paused => session.press(input),
+                Some(Command::Press(_)) => {}
+                Some(Command::Pause) => {
+                    paused = !paused;
+                    frontend.set_paused(paused);
+                }
+                Some(Command::Quit) => return Ok(()),
+                None if timeout.is_zero() => break,
+                None => {}
+            }
+        }
+
+        next_frame = after(next_frame);
+        // the same as under the window's pause menu
+        if !paused || session.connection.is_some() {
+            session.tick();
+        }
+        session.process_events();
+
+        frontend.draw(session)?;
+    }
+}
+
+// when the frame after `frame` is due, skipping ahead rather than racing to catch up
+pub fn after(frame: Instant) -> Instant {
+    let now = Instant::now();
+    if now > frame + game::FRAME * MAX_LAG {
+        now + game::FRAME
+    } else {
+        frame + game::FRAME
+    }
+}
+
+// draws nothing and stops once every game is over, for AI matches and broadcasting
+#[derive(Default)]
+pub struct Null {
+    over: bool,
+}
+
+impl Frontend for Null {
+    fn draw(&mut self, session: &Session) -> Result<()> {
+        self.over = session.is_over();
+        Ok(())
+    }
+
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Command>> {
+        if self.over {
+            return Ok(Some(Command::Quit));
+        }
+        std::thread::sleep(timeout);
+        Ok(None)
+    }
+}
+
+// keeps an image of the game on disk, to watch it somewhere without a window
+pub struct Image {
+    renderer: Box<dyn ImageRenderer>,
+    output: PathBuf,
+    last_saved: Option<Instant>,
+    null: Null,
+}
+
+impl Image {
+    pub fn new(renderer: Box<dyn ImageRenderer>, output: PathBuf) -> Self {
+        Self {
+            renderer,
+            output,
+            last_saved: None,
+            null: Null::default(),
+        }
+    }
+}
+
+impl Frontend for Image {
+    fn draw(&mut self, session: &Session) -> Result<()> {
+        self.null.draw(session)?;
+        // the last frame is always saved, however soon after the one before
+        let due = self
+            .last_saved
+            .is_none_or(|saved| saved.elapsed() >= IMAGE_INTERVAL);
+        if due || session.is_over() {
+            self.renderer
+                .render_to_image(&session.to_instances(), &session.overlay_instances())?
+                .save(&self.output)?;
+            self.last_saved = Some(Instant::now());
+        }
+        Ok(())
+    }
+
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Command>> {
+        self.null.poll(timeout)
+    }
+}
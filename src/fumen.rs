@@ -0,0 +1,115 @@
+use anyhow::{anyhow, bail, Result};
+
+use super::tetrimino::Color;
+
+// only the field of the first page is read, comments and pieces are skipped
+const VERSION: &str = "v115@";
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub const WIDTH: usize = 10;
+// rows above the floor, fumen keeps one more below it for garbage
+pub const HEIGHT: usize = 23;
+const FIELD_CELLS: usize = WIDTH * (HEIGHT + 1);
+
+pub fn is_fumen(text: &str) -> bool {
+    text.contains(VERSION)
+}
+
+// the first page's field as rows from the bottom up
+pub fn decode(text: &str) -> Result<Vec<Vec<Color>>> {
+    let start = text
+        .find(VERSION)
+        .ok_or_else(|| anyhow!("Only {} fumen data is supported", VERSION))?;
+    let mut values = text[start + VERSION.len()..]
+        .chars()
+        .take_while(|c| !c.is_whitespace() && *c != '&')
+        // fumen breaks long data up with question marks
+        .filter(|&c| c != '?')
+        .map(|c| {
+            ALPHABET
+                .iter()
+                .position(|&a| a as char == c)
+                .ok_or_else(|| anyhow!("Bad fumen character: {}", c))
+        });
+    let mut poll = |digits: u32| -> Result<usize> {
+        let mut value = 0;
+        for digit in 0..digits {
+            let next = values
+                .next()
+                .ok_or_else(|| anyhow!("Fumen data ends too early"))??;
+            value += next * 64usize.pow(digit);
+        }
+        Ok(value)
+    };
+
+    // runs of cells from the top left, each the difference from the previous page
+    let mut field = vec![0; FIELD_CELLS];
+    let mut index = 0;
+    while index < FIELD_CELLS {
+        let run = poll(2)?;
+        let (diff, count) = (run / FIELD_CELLS, run % FIELD_CELLS + 1);
+        if index + count > FIELD_CELLS {
+            bail!("Fumen field runs past the bottom");
+        }
+        for cell in &mut field[index..index + count] {
+            *cell = diff as i32 - 8;
+        }
+        index += count;
+    }
+
+    (0..HEIGHT)
+        .map(|row| {
+            let top = (HEIGHT - 1 - row) * WIDTH;
+            field[top..top + WIDTH]
+                .iter()
+                .map(|&cell| color(cell))
+                .collect()
+        })
+        .collect()
+}
+
+fn color(cell: i32) -> Result<Color> {
+    Ok(match cell {
+        0 => Color::Empty,
+        1 => Color::LightBlue,
+        2 => Color::Orange,
+        3 => Color::Yellow,
+        4 => Color::Red,
+        5 => Color::Purple,
+        6 => Color::Blue,
+        7 => Color::Green,
+        8 => Color::Garbage,
+        _ => bail!("Bad fumen block: {}", cell),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(rows: &[Vec<Color>], y: usize) -> String {
+        rows[y].iter().map(|color| color.to_char()).collect()
+    }
+
+    #[test]
+    fn empty_field() {
+        let rows = decode("v115@vhAAgH").unwrap();
+        assert_eq!(rows.len(), HEIGHT);
+        assert!((0..HEIGHT).all(|y| row(&rows, y) == ".........."));
+    }
+
+    #[test]
+    fn decodes_the_first_page() {
+        // runs of 220 empty cells, 4 I, 6 garbage and the 10 under the floor
+        let rows = decode("https://fumen.zui.jp/?v115@bhzhF8JeAgH").unwrap();
+        assert_eq!(row(&rows, 0), "IIIIGGGGGG");
+        assert!((1..HEIGHT).all(|y| row(&rows, y) == ".........."));
+    }
+
+    #[test]
+    fn rejects_broken_data() {
+        assert!(decode("v114@vhAAgH").is_err());
+        assert!(decode("v115@v!AAgH").is_err());
+        assert!(decode("v115@bh").is_err());
+    }
+}
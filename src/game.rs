@@ -0,0 +1,442 @@
+use std::time::Duration;
+
+use super::board::{Board, BoardEvent};
+use super::garbage::{AttackTable, GarbageGenerator, GarbageQueue};
+use super::input::Input;
+use super::mode::{self, Mode};
+
+pub const FRAME: Duration = Duration::from_micros(16_667);
+
+#[derive(Clone)]
+pub struct Game {
+    pub board: Board,
+    pub mode: Mode,
+    pub score: u32,
+    pub lines: u32,
+    pub pieces: u32,
+    pub clock: Duration,
+    pub over: bool,
+
+    gravity_timer: Duration,
+    // frames the stack waits after a clear, and before every new piece
+    pub line_clear_delay: u32,
+    pub are: u32,
+    entry_timer: u32,
+    // what happened on the board this frame, for the animations
+    events: Vec<BoardEvent>,
+
+    garbage: GarbageGenerator,
+    garbage_rows: u32,
+    garbage_spawned: u32,
+    garbage_cleared: u32,
+
+    attack_table: AttackTable,
+    pub incoming: GarbageQueue,
+    outgoing: u32,
+    combo: Option<usize>,
+    back_to_back: bool,
+}
+
+impl Game {
+    pub fn new(mut board: Board, mode: Mode, seed: u64) -> Self {
+        board.seed(seed);
+        if board.current_tetrimino.is_none() {
+            board.spawn_tetrimino();
+        }
+
+        let messiness = match mode {
+            Mode::Cheese { messiness, .. } => messiness,
+            _ => 1.0,
+        };
+        let garbage_delay = match mode {
+            Mode::Versus { garbage_delay } => frames(garbage_delay),
+            _ => 0,
+        };
+
+        let mut game = Self {
+            board,
+            mode,
+            score: 0,
+            lines: 0,
+            pieces: 0,
+            clock: Duration::ZERO,
+            over: false,
+            gravity_timer: Duration::ZERO,
+            line_clear_delay: 0,
+            are: 0,
+            entry_timer: 0,
+            events: Vec::new(),
+            // a separate stream so garbage holes don't shift the piece sequence
+            garbage: GarbageGenerator::new(messiness, seed.wrapping_add(1)),
+            garbage_rows: 0,
+            garbage_spawned: 0,
+            garbage_cleared: 0,
+            attack_table: AttackTable::default(),
+            incoming: GarbageQueue::new(garbage_delay),
+            outgoing: 0,
+            combo: None,
+            back_to_back: false,
+        };
+        game.refill_cheese();
+        game
+    }
+
+    // without delays the next piece spawns the moment the last one locks
+    pub fn with_delays(mut self, line_clear_delay: u32, are: u32) -> Self {
+        self.line_clear_delay = line_clear_delay;
+        self.are = are;
+        self.board.set_entry_delay(line_clear_delay > 0 || are > 0);
+        self
+    }
+
+    pub fn events(&self) -> &[BoardEvent] {
+        &self.events
+    }
+
+    pub fn apply_input(&mut self, input: Input) {
+        self.events.clear();
+        if self.over || input.is_empty() {
+            return;
+        }
+
+        let board = &mut self.board;
+        if input.contains(Input::HOLD) {
+            board.hold();
+        }
+        if input.contains(Input::ROTATE_LEFT) {
+            board.rotate_left();
+        }
+        if input.contains(Input::ROTATE_RIGHT) {
+            board.rotate_right();
+        }
+        if input.contains(Input::LEFT) {
+            board.move_left();
+        }
+        if input.contains(Input::RIGHT) {
+            board.move_right();
+        }
+        if input.contains(Input::SOFT_DROP) {
+            board.move_down();
+        }
+        if input.contains(Input::HARD_DROP) {
+            board.hard_drop();
+        }
+
+        self.process_events();
+    }
+
+    pub fn tick(&mut self) {
+        if self.over {
+            return;
+        }
+
+        self.clock += FRAME;
+        if let Some(limit) = self.mode.time_limit() {
+            if self.clock >= limit {
+                self.clock = limit;
+                self.over = true;
+                return;
+            }
+        }
+
+        self.incoming.tick();
+
+        // cleared rows collapse after the line clear delay, the next piece enters after ARE
+        if self.board.current_tetrimino.is_none() {
+            if self.entry_timer <= self.are && !self.board.clearing().is_empty() {
+                self.board.finish_clear();
+                self.rows_cleared();
+            }
+            if self.over {
+                return;
+            }
+
+            if self.entry_timer > 0 {
+                self.entry_timer -= 1;
+            } else {
+                self.gravity_timer = Duration::ZERO;
+                self.board.spawn_tetrimino();
+                self.process_events();
+            }
+            return;
+        }
+
+        self.gravity_timer += FRAME;
+        let interval = gravity_interval(self.level());
+        while self.gravity_timer >= interval && !self.over {
+            self.gravity_timer -= interval;
+            self.board.move_down();
+            self.process_events();
+        }
+    }
+
+    fn refill_cheese(&mut self) {
+        let goal = match self.mode {
+            Mode::Cheese { lines, .. } => lines,
+            _ => return,
+        };
+
+        while self.garbage_rows < mode::CHEESE_HEIGHT && self.garbage_spawned < goal {
+            let hole = self.garbage.next_hole(self.board.width());
+            self.board.insert_garbage_row(hole);
+            self.garbage_rows += 1;
+            self.garbage_spawned += 1;
+        }
+    }
+
+    pub fn receive_garbage(&mut self, lines: u32) {
+        self.incoming.push(lines);
+    }
+
+    pub fn take_outgoing(&mut self) -> u32 {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    fn apply_incoming(&mut self) {
+        for lines in self.incoming.take_ready() {
+            let hole = self.garbage.next_hole(self.board.width());
+            for _ in 0..lines {
+                self.board.insert_garbage_row(hole);
+            }
+            self.events.push(BoardEvent::GarbageRose { lines });
+        }
+    }
+
+    pub fn garbage_remaining(&self) -> Option<u32> {
+        match self.mode {
+            Mode::Cheese { lines, .. } => Some(lines.saturating_sub(self.garbage_cleared)),
+            _ => None,
+        }
+    }
+
+    pub fn level(&self) -> u32 {
+        match self.mode {
+            Mode::Marathon { start_level, .. } => {
+                (start_level + self.lines / 10).min(mode::MARATHON_MAX_LEVEL)
+            }
+            _ => 1,
+        }
+    }
+
+    pub fn process_events(&mut self) {
+        for event in self.board.take_events() {
+            self.events.push(event.clone());
+            if let BoardEvent::Locked { lines, .. } = event {
+                self.pieces += 1;
+                self.entry_timer = self.are + if lines > 0 { self.line_clear_delay } else { 0 };
+            }
+
+            match event {
+                BoardEvent::Locked { lines: 0, .. } => {
+                    // garbage only rises when the player doesn't clear
+                    self.combo = None;
+                    self.apply_incoming();
+                }
+                BoardEvent::Locked {
+                    lines,
+                    tspin,
+                    perfect_clear,
+                    ..
+                } => {
+                    let combo = self.combo.map_or(0, |combo| combo + 1);
+                    self.combo = Some(combo);
+
+                    let difficult = tspin || lines >= 4;
+                    let back_to_back = difficult && self.back_to_back;
+                    self.back_to_back = difficult;
+
+                    let attack =
+                        self.attack_table
+                            .attack(lines, tspin, combo, back_to_back, perfect_clear);
+                    self.outgoing += self.incoming.cancel(attack);
+
+                    self.score += line_clear_score(lines, tspin) * self.level();
+                    self.lines += lines as u32;
+
+                    // with a line clear delay the rows are still there until `enter`
+                    if self.board.clearing().is_empty() {
+                        self.rows_cleared();
+                    }
+                }
+                BoardEvent::Cleared { .. }
+                | BoardEvent::HardDropped { .. }
+                | BoardEvent::GarbageRose { .. } => (),
+                BoardEvent::ToppedOut => self.over = true,
+            }
+        }
+    }
+
+    fn rows_cleared(&mut self) {
+        let garbage_rows = self.board.count_garbage_rows() as u32;
+        self.garbage_cleared += self.garbage_rows.saturating_sub(garbage_rows);
+        self.garbage_rows = garbage_rows;
+        self.refill_cheese();
+
+        if self.mode.goal_reached(self.lines, self.garbage_cleared) {
+            self.over = true;
+        }
+    }
+
+    pub fn remaining(&self) -> Option<Duration> {
+        self.mode
+            .time_limit()
+            .map(|limit| limit.saturating_sub(self.clock))
+    }
+
+    pub fn pps(&self) -> f32 {
+        let secs = self.clock.as_secs_f32();
+        if secs > 0.0 {
+            self.pieces as f32 / secs
+        } else {
+            0.0
+        }
+    }
+
+    // label and value pairs shown next to the board
+    pub fn hud(&self) -> Vec<(&'static str, String)> {
+        let time = match self.remaining() {
+            Some(remaining) => {
+                let secs = remaining.as_millis().div_ceil(1000);
+                format!("{}:{:02}", secs / 60, secs % 60)
+            }
+            None => {
+                let centis = self.clock.as_millis() / 10;
+                format!(
+                    "{}:{:02}.{:02}",
+                    centis / 6000,
+                    centis / 100 % 60,
+                    centis % 100
+                )
+            }
+        };
+
+        let mut hud = vec![
+            ("Mode", self.mode.name().to_string()),
+            ("Time", time),
+            ("Score", self.score.to_string()),
+            ("Level", self.level().to_string()),
+            ("Lines", self.lines.to_string()),
+            ("PPS", format!("{:.2}", self.pps())),
+        ];
+        if let Some(garbage) = self.garbage_remaining() {
+            hud.push(("Garbage", garbage.to_string()));
+        }
+        if let Mode::Versus { .. } = self.mode {
+            hud.push(("Incoming", self.incoming.total().to_string()));
+        }
+        if self.over {
+            hud.push(("Game Over", String::new()));
+        }
+        hud
+    }
+
+    pub fn status(&self) -> String {
+        let mut status = format!(
+            "{} - Score {} - Lines {} - Level {}",
+            self.mode.name(),
+            self.score,
+            self.lines,
+            self.level()
+        );
+
+        if let Some(garbage) = self.garbage_remaining() {
+            status.push_str(&format!(" - Garbage Left {}", garbage));
+        }
+        if let Mode::Versus { .. } = self.mode {
+            status.push_str(&format!(" - Incoming {}", self.incoming.total()));
+        }
+        if let Some(remaining) = self.remaining() {
+            // round up so the timer reads 0:00 only once time is actually up
+            let secs = remaining.as_millis().div_ceil(1000);
+            status.push_str(&format!(" - {}:{:02}", secs / 60, secs % 60));
+        }
+        if self.over {
+            status.push_str(" - Game Over");
+        }
+
+        status
+    }
+}
+
+pub fn line_clear_score(lines: usize, tspin: bool) -> u32 {
+    match (lines, tspin) {
+        (0, _) => 0,
+        (1, false) => 100,
+        (2, false) => 300,
+        (3, false) => 500,
+        (_, false) => 800,
+        (1, true) => 800,
+        (2, true) => 1200,
+        (_, true) => 1600,
+    }
+}
+
+pub fn frames(duration: Duration) -> u32 {
+    (duration.as_secs_f64() / FRAME.as_secs_f64()).round() as u32
+}
+
+// guideline gravity: seconds per row = (0.8 - (level - 1) * 0.007) ^ (level - 1)
+pub fn gravity_interval(level: u32) -> Duration {
+    let level = level.max(1) as f64;
+    let secs = (0.8 - (level - 1.0) * 0.007).powf(level - 1.0);
+    Duration::from_secs_f64(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tetrimino::Tetrimino;
+
+    // every row's hole in the same column
+    fn cheese(lines: u32) -> Game {
+        let board = Board::new(10, 40, 1.0, 0.0, 0.0);
+        let mode = Mode::Cheese {
+            lines,
+            messiness: 0.0,
+        };
+        Game::new(board, mode, 1)
+    }
+
+    // a vertical I straight down the holes
+    fn dig(game: &mut Game) {
+        let hole = game
+            .board
+            .get_row(0)
+            .iter()
+            .position(|block| block.is_empty());
+        let hole = hole.unwrap() as i32;
+        game.board
+            .set_current_tetrimino(&Tetrimino::I, hole - 2, 10, 1);
+        game.apply_input(Input::HARD_DROP);
+    }
+
+    #[test]
+    fn cheese_fills_up_to_its_height() {
+        let game = cheese(18);
+        assert_eq!(
+            game.board.count_garbage_rows(),
+            mode::CHEESE_HEIGHT as usize
+        );
+        assert_eq!(cheese(3).board.count_garbage_rows(), 3);
+    }
+
+    #[test]
+    fn cleared_garbage_is_refilled_until_the_goal() {
+        let mut game = cheese(12);
+        dig(&mut game);
+
+        // four rows dug, only two more left to come
+        assert_eq!(game.garbage_remaining(), Some(8));
+        assert_eq!(game.board.count_garbage_rows(), 8);
+        assert!(!game.over);
+    }
+
+    #[test]
+    fn digging_out_the_last_row_wins() {
+        let mut game = cheese(1);
+        dig(&mut game);
+
+        assert_eq!(game.garbage_remaining(), Some(0));
+        assert!(game.over);
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[derive(Clone)]
+pub struct GarbageGenerator {
+    hole: Option<usize>,
+    messiness: f32,
+    rng: StdRng,
+}
+
+impl GarbageGenerator {
+    pub fn new(messiness: f32, seed: u64) -> Self {
+        Self {
+            hole: None,
+            messiness: messiness.clamp(0.0, 1.0),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    // messiness is the chance that the hole moves to a different column
+    pub fn next_hole(&mut self, width: usize) -> usize {
+        let hole = match self.hole {
+            Some(hole) if self.rng.gen::<f32>() >= self.messiness => hole,
+            Some(hole) => (hole + self.rng.gen_range(1, width)) % width,
+            None => self.rng.gen_range(0, width),
+        };
+        self.hole = Some(hole);
+        hole
+    }
+}
+
+#[derive(Clone)]
+pub struct AttackTable {
+    pub lines: [u32; 5],
+    pub tspin: [u32; 4],
+    pub combo: Vec<u32>,
+    pub back_to_back: u32,
+    pub perfect_clear: u32,
+}
+
+impl Default for AttackTable {
+    fn default() -> Self {
+        Self {
+            lines: [0, 0, 1, 2, 4],
+            tspin: [0, 2, 4, 6],
+            combo: vec![0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5],
+            back_to_back: 1,
+            perfect_clear: 10,
+        }
+    }
+}
+
+impl AttackTable {
+    // combo counts consecutive clears, starting at 0 for the first one
+    pub fn attack(
+        &self,
+        lines: usize,
+        tspin: bool,
+        combo: usize,
+        back_to_back: bool,
+        perfect_clear: bool,
+    ) -> u32 {
+        if lines == 0 {
+            return 0;
+        }
+
+        let mut attack = if tspin {
+            self.tspin[lines.min(self.tspin.len() - 1)]
+        } else {
+            self.lines[lines.min(self.lines.len() - 1)]
+        };
+
+        if let Some(bonus) = self.combo.get(combo).or_else(|| self.combo.last()) {
+            attack += bonus;
+        }
+        if back_to_back {
+            attack += self.back_to_back;
+        }
+        if perfect_clear {
+            attack += self.perfect_clear;
+        }
+
+        attack
+    }
+}
+
+#[derive(Clone)]
+struct PendingGarbage {
+    lines: u32,
+    frames_left: u32,
+}
+
+#[derive(Clone)]
+pub struct GarbageQueue {
+    pending: VecDeque<PendingGarbage>,
+    delay: u32,
+}
+
+impl GarbageQueue {
+    pub fn new(delay: u32) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            delay,
+        }
+    }
+
+    pub fn push(&mut self, lines: u32) {
+        if lines > 0 {
+            self.pending.push_back(PendingGarbage {
+                lines,
+                frames_left: self.delay,
+            });
+        }
+    }
+
+    // cancels the oldest garbage first and returns whatever attack is left over
+    pub fn cancel(&mut self, mut attack: u32) -> u32 {
+        while attack > 0 {
+            let Some(front) = self.pending.front_mut() else {
+                break;
+            };
+
+            let cancelled = front.lines.min(attack);
+            front.lines -= cancelled;
+            attack -= cancelled;
+            if front.lines == 0 {
+                self.pending.pop_front();
+            }
+        }
+        attack
+    }
+
+    pub fn tick(&mut self) {
+        for garbage in self.pending.iter_mut() {
+            garbage.frames_left = garbage.frames_left.saturating_sub(1);
+        }
+    }
+
+    pub fn take_ready(&mut self) -> Vec<u32> {
+        let mut ready = Vec::new();
+        while let Some(garbage) = self.pending.front() {
+            if garbage.frames_left > 0 {
+                break;
+            }
+            ready.push(garbage.lines);
+            self.pending.pop_front();
+        }
+        ready
+    }
+
+    pub fn total(&self) -> u32 {
+        self.pending.iter().map(|garbage| garbage.lines).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_garbage_keeps_its_hole() {
+        let mut generator = GarbageGenerator::new(0.0, 3);
+        let first = generator.next_hole(10);
+        assert!((0..20).all(|_| generator.next_hole(10) == first));
+    }
+
+    #[test]
+    fn messy_garbage_always_moves_its_hole() {
+        let mut generator = GarbageGenerator::new(1.0, 3);
+        let mut last = generator.next_hole(10);
+        for _ in 0..20 {
+            let hole = generator.next_hole(10);
+            assert!(hole < 10 && hole != last);
+            last = hole;
+        }
+    }
+
+    #[test]
+    fn cancels_oldest_garbage_first() {
+        let mut queue = GarbageQueue::new(0);
+        queue.push(1);
+        queue.push(3);
+
+        // all of the first batch and one line of the second
+        assert_eq!(queue.cancel(2), 0);
+        assert_eq!(queue.take_ready(), vec![2]);
+
+        // whatever's left over is sent on
+        queue.push(4);
+        assert_eq!(queue.cancel(7), 3);
+        assert_eq!(queue.total(), 0);
+    }
+
+    #[test]
+    fn garbage_waits_out_its_delay() {
+        let mut queue = GarbageQueue::new(2);
+        queue.push(1);
+        queue.tick();
+        assert!(queue.take_ready().is_empty());
+        queue.tick();
+        assert_eq!(queue.take_ready(), vec![1]);
+    }
+
+    #[test]
+    fn attack_table() {
+        let table = AttackTable::default();
+        assert_eq!(table.attack(0, false, 5, true, false), 0);
+        assert_eq!(table.attack(1, false, 0, false, false), 0);
+        assert_eq!(table.attack(2, false, 0, false, false), 1);
+        assert_eq!(table.attack(4, false, 0, false, false), 4);
+        assert_eq!(table.attack(4, false, 0, true, false), 5);
+        assert_eq!(table.attack(2, true, 0, false, false), 4);
+        assert_eq!(table.attack(3, true, 0, true, false), 7);
+        // combos past the end of the table keep its last bonus
+        assert_eq!(table.attack(1, false, 3, false, false), 1);
+        assert_eq!(table.attack(1, false, 40, false, false), 5);
+        assert_eq!(table.attack(1, false, 0, false, true), 10);
+    }
+}
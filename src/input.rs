@@ -0,0 +1,66 @@
+use super::battle::Targeting;
+
+// the targeting choice is stored as its index plus one in these bits
+const TARGETING_SHIFT: u16 = 6;
+const TARGETING_MASK: u16 = 0b111 << TARGETING_SHIFT;
+
+// everything a player pressed during one frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Input(pub u16);
+
+impl Input {
+    pub const NONE: Input = Input(0);
+    pub const LEFT: Input = Input(1);
+    pub const RIGHT: Input = Input(1 << 1);
+    pub const ROTATE_LEFT: Input = Input(1 << 2);
+    pub const ROTATE_RIGHT: Input = Input(1 << 3);
+    pub const SOFT_DROP: Input = Input(1 << 4);
+    pub const HARD_DROP: Input = Input(1 << 5);
+    // bits 6 to 8 hold the targeting choice
+    pub const HOLD: Input = Input(1 << 9);
+
+    pub fn contains(self, other: Input) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn target(targeting: Targeting) -> Input {
+        let index = Targeting::ALL
+            .iter()
+            .position(|&choice| choice == targeting)
+            .unwrap() as u16;
+        Input((index + 1) << TARGETING_SHIFT)
+    }
+
+    pub fn targeting(self) -> Option<Targeting> {
+        let index = (self.0 & TARGETING_MASK) >> TARGETING_SHIFT;
+        Targeting::ALL
+            .get((index as usize).checked_sub(1)?)
+            .copied()
+    }
+
+    // a later targeting choice replaces an earlier one instead of mixing bits
+    pub fn press(&mut self, other: Input) {
+        if other.targeting().is_some() {
+            self.0 &= !TARGETING_MASK;
+        }
+        self.0 |= other.0;
+    }
+}
+
+impl std::ops::BitOr for Input {
+    type Output = Input;
+
+    fn bitor(self, rhs: Input) -> Input {
+        Input(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for Input {
+    fn bitor_assign(&mut self, rhs: Input) {
+        self.0 |= rhs.0;
+    }
+}
@@ -1,18 +1,53 @@
+mod ai;
+mod animation;
+mod app;
+mod battle;
 mod board;
+mod capture;
+mod frontend;
+mod fumen;
+mod game;
+mod garbage;
+mod input;
+mod mode;
+mod net;
+mod options;
+mod palette;
 mod renderer;
+mod replay;
+mod rollback;
+pub mod server;
+mod session;
+mod skin;
+mod spectate;
 mod tetrimino;
+mod tui;
 
 use std::{
     sync::{Arc, Mutex},
-    time::Duration,
+    time::Instant,
 };
 
-use renderer::Renderer;
+use ai::Ai;
+use app::{Action, App};
+use battle::Targeting;
+use board::Board;
+use frontend::{Frontend, FrontendKind};
+use game::Game;
+use input::Input;
+use mode::Mode;
+use net::{Connection, NetRole};
+use options::Options;
+use renderer::{tiles, ImageRenderer, Renderer};
+use replay::Recorder;
+use session::{Controller, Player, Session, HUD_COLUMNS};
+use skin::Skin;
+use spectate::Broadcaster;
 
 use anyhow::Result;
 use winit::{
-    dpi::LogicalSize,
-    event::{Event, VirtualKeyCode, WindowEvent},
+    dpi::{LogicalSize, PhysicalSize},
+    event::{ElementState, Event, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::Window,
 };
@@ -21,123 +56,509 @@ pub async fn run() -> Result<()> {
     #[cfg(debug_assertions)]
     env_logger::init();
 
-    let event_loop = EventLoop::new();
-    let window = Window::new(&event_loop)?;
-    window.set_title("Tetris In Rust");
-    window.set_resizable(false);
-    window.set_inner_size(LogicalSize::new(500.0, 700.0));
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut options = Options::parse(args.clone())?;
+    // a replay needs the seed written down before the game starts
+    if options.record.is_some() {
+        options.seed.get_or_insert_with(rand::random);
+    }
 
-    let tile_size = 20.0 / 200.0;
-    let width = 10;
-    let height = 40;
+    if options.list_rooms {
+        let Some(NetRole::Server { addr, .. }) = &options.net else {
+            anyhow::bail!("--list-rooms needs --server");
+        };
+        for room in Connection::list_rooms(addr)? {
+            let state = if room.started { "playing" } else { "waiting" };
+            println!("{} {}/{} {}", room.name, room.players, room.capacity, state);
+        }
+        return Ok(());
+    }
 
-    let mut board = board::Board::new(width, height, tile_size, tile_size * 5.0, tile_size * 10.0);
+    if let Some(input) = &options.render {
+        let skin = load_skin(&options);
+        let output = options.output.as_deref().unwrap_or("board.png");
+        let path = std::path::Path::new(output);
+        capture::render_board(input, path, &skin, options.grid, options.cpu).await?;
+        println!("Saved {}", output);
+        return Ok(());
+    }
 
-    board.place_tetrimino(&tetrimino::Tetrimino::I, 0, -3, 0);
-    board.place_tetrimino(&tetrimino::Tetrimino::I, 4, -3, 0);
-    board.place_tetrimino(&tetrimino::Tetrimino::I, 0, -2, 0);
-    board.place_tetrimino(&tetrimino::Tetrimino::I, 4, -2, 0);
-    board.place_tetrimino(&tetrimino::Tetrimino::I, 0, -1, 0);
-    board.place_tetrimino(&tetrimino::Tetrimino::I, 4, -1, 0);
-    board.place_tetrimino(&tetrimino::Tetrimino::I, 0, 0, 0);
-    board.place_tetrimino(&tetrimino::Tetrimino::I, 4, 0, 0);
+    if let Some(path) = &options.replay {
+        let output = options.output.as_deref().unwrap_or("replay.gif");
+        replay::export(path, std::path::Path::new(output), &options)?;
+        println!("Saved {}", output);
+        return Ok(());
+    }
 
-    board.place_tetrimino(&tetrimino::Tetrimino::I, 6, -1, 1);
+    let skin = load_skin(&options);
+    // the window isn't a `Frontend`, winit owns its event loop and the menus live in `App`
+    let (session, frontend): (Session, Box<dyn Frontend>) = match options.frontend {
+        FrontendKind::Window => return run_window(options, args, skin).await,
+        FrontendKind::Terminal => {
+            let session = command_line_session(&options, &args)?;
+            (session, Box::new(tui::Terminal::new()?))
+        }
+        FrontendKind::Image => {
+            let session = command_line_session(&options, &args)?;
+            let frontend = image_frontend(session.players.len(), &options, &skin).await;
+            (session, Box::new(frontend))
+        }
+        FrontendKind::Null => {
+            let session = command_line_session(&options, &args)?;
+            (session, Box::new(frontend::Null::default()))
+        }
+    };
+    run_frontend(session, frontend)
+}
+
+// the menus and games in a window, which has to hand the thread over to winit
+async fn run_window(options: Options, args: Vec<String>, skin: Skin) -> Result<()> {
+    // the title screen comes first unless the command line already picked a game
+    let session = if options.play_now {
+        Some(command_line_session(&options, &args)?)
+    } else {
+        None
+    };
+    let app = App::new(options, session);
 
-    board.set_current_tetrimino(&tetrimino::Tetrimino::I, 3, 17, 0);
+    let event_loop = EventLoop::new();
+    let window = Window::new(&event_loop)?;
+    window.set_title("Tetris In Rust");
 
-    let renderer = Renderer::init(&window, board.num_vertices()).await?;
+    let mut players = app.player_count();
+    let size = window_size(players);
+    window.set_inner_size(size);
+    let view = view_size(size);
+    let mut renderer = Renderer::init(&window, view, &skin).await?;
+    if app.options().dev {
+        renderer.enable_hot_reload(app.options().skin.as_deref(), skin_overrides(app.options()));
+    }
 
-    let mut previous_key_pressed: Option<VirtualKeyCode> = None;
+    let mut title = String::new();
 
-    let board_arc = Arc::new(Mutex::new(board));
+    let app_arc = Arc::new(Mutex::new(app));
 
-    let board_cloned = board_arc.clone();
-    let _timer_thread = std::thread::spawn(move || loop {
-        std::thread::sleep(Duration::from_secs(1));
-        let mut board = board_cloned.lock().unwrap();
+    let app_cloned = app_arc.clone();
+    let _timer_thread = std::thread::spawn(move || {
+        let mut next_frame = Instant::now() + game::FRAME;
+        loop {
+            std::thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+            let mut app = app_cloned.lock().unwrap();
 
-        board.update();
+            // sleeping long or waiting on the lock is made up with extra frames
+            while Instant::now() >= next_frame {
+                app.tick();
+                next_frame = frontend::after(next_frame);
+            }
+        }
     });
 
-    let board_cloned = board_arc;
+    let app_cloned = app_arc;
     event_loop.run(move |event, _, control_flow| {
-        let mut board = board_cloned.lock().unwrap();
-
+        let mut app = app_cloned.lock().unwrap();
         match event {
             Event::WindowEvent {
                 event: window_event,
                 ..
             } => match window_event {
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                WindowEvent::Resized(size) => renderer.resize(size),
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    renderer.resize(*new_inner_size)
+                }
 
-                WindowEvent::KeyboardInput { input, .. } => match input.virtual_keycode {
-                    Some(VirtualKeyCode::Escape) => *control_flow = ControlFlow::Exit,
-                    Some(VirtualKeyCode::Q) => match previous_key_pressed {
-                        Some(VirtualKeyCode::Q) => {
-                            previous_key_pressed = None;
-                        }
-                        _ => {
-                            board.rotate_left();
-                            previous_key_pressed = Some(VirtualKeyCode::Q);
-                        }
-                    },
-                    Some(VirtualKeyCode::E) => match previous_key_pressed {
-                        Some(VirtualKeyCode::E) => {
-                            previous_key_pressed = None;
-                        }
-                        _ => {
-                            board.rotate_right();
-                            previous_key_pressed = Some(VirtualKeyCode::E);
-                        }
-                    },
-                    Some(VirtualKeyCode::A) => match previous_key_pressed {
-                        Some(VirtualKeyCode::A) => {
-                            previous_key_pressed = None;
-                        }
-                        _ => {
-                            board.move_left();
-                            previous_key_pressed = Some(VirtualKeyCode::A);
-                        }
-                    },
-                    Some(VirtualKeyCode::D) => match previous_key_pressed {
-                        Some(VirtualKeyCode::D) => {
-                            previous_key_pressed = None;
-                        }
-                        _ => {
-                            board.move_right();
-                            previous_key_pressed = Some(VirtualKeyCode::D);
+                WindowEvent::CloseRequested => {
+                    app.quit();
+                    *control_flow = ControlFlow::Exit
+                }
+
+                WindowEvent::KeyboardInput { input, .. } => {
+                    if input.state != ElementState::Pressed {
+                        return;
+                    }
+                    let Some(keycode) = input.virtual_keycode else {
+                        return;
+                    };
+                    if keycode == VirtualKeyCode::F12 {
+                        let path = capture::screenshot_path();
+                        let result = renderer
+                            .render_to_image(&app.to_instances(), &app.overlay_instances())
+                            .and_then(|image| Ok(image.save(&path)?));
+                        match result {
+                            Ok(()) => println!("Saved {}", path.display()),
+                            Err(e) => eprintln!("Screenshot: {}", e),
                         }
-                    },
-                    Some(VirtualKeyCode::S) => match previous_key_pressed {
-                        Some(VirtualKeyCode::S) => {
-                            previous_key_pressed = None;
+                        return;
+                    }
+
+                    if app.screen() == app::Screen::Playing && keycode != VirtualKeyCode::Escape {
+                        if let Some(pressed) = game_input(keycode) {
+                            app.press(pressed);
                         }
-                        _ => {
-                            board.move_down();
-                            previous_key_pressed = Some(VirtualKeyCode::D);
+                        return;
+                    }
+                    let Some(key) = menu_key(keycode) else {
+                        return;
+                    };
+                    match app.key(key) {
+                        Some(Action::Quit) => *control_flow = ControlFlow::Exit,
+                        Some(Action::Restyle) => {
+                            if let Err(e) = renderer.set_skin(&load_skin(app.options())) {
+                                eprintln!("Skin: {:#}", e);
+                            }
                         }
-                    },
-
-                    _ => (),
-                },
+                        None => {}
+                    }
+                }
 
                 _ => (),
             },
 
             Event::RedrawRequested(_) => {
-                let result = renderer.render(&board.to_vertices());
-                match result {
-                    Ok(_) => {}
-                    Err(wgpu::SurfaceError::Lost) => panic!("Surface Lost"),
-                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    Err(e) => eprintln!("{:?}", e),
+                let result = renderer.show(&app.to_instances(), &app.overlay_instances());
+                if let Err(e) = result {
+                    eprintln!("{}", e);
+                    app.quit();
+                    *control_flow = ControlFlow::Exit;
                 }
             }
             Event::MainEventsCleared => {
+                app.process_events();
+
+                // versus and battles need the wider window
+                if app.player_count() != players {
+                    players = app.player_count();
+                    let size = window_size(players);
+                    renderer.view = view_size(size);
+                    window.set_inner_size(size);
+                    renderer.resize(renderer.size);
+                }
+
+                let status = match app.status() {
+                    Some(status) => format!("Tetris In Rust - {}", status),
+                    None => String::from("Tetris In Rust"),
+                };
+                if status != title {
+                    window.set_title(&status);
+                    title = status;
+                }
+
                 window.request_redraw();
             }
             _ => (),
         }
     });
 }
+
+// the keys that play the game
+fn game_input(keycode: VirtualKeyCode) -> Option<Input> {
+    match keycode {
+        VirtualKeyCode::Q => Some(Input::ROTATE_LEFT),
+        VirtualKeyCode::E => Some(Input::ROTATE_RIGHT),
+        VirtualKeyCode::A => Some(Input::LEFT),
+        VirtualKeyCode::D => Some(Input::RIGHT),
+        VirtualKeyCode::S => Some(Input::SOFT_DROP),
+        VirtualKeyCode::Space => Some(Input::HARD_DROP),
+        VirtualKeyCode::C | VirtualKeyCode::LShift => Some(Input::HOLD),
+        VirtualKeyCode::Key1 => Some(Input::target(Targeting::Random)),
+        VirtualKeyCode::Key2 => Some(Input::target(Targeting::Attackers)),
+        VirtualKeyCode::Key3 => Some(Input::target(Targeting::KOs)),
+        VirtualKeyCode::Key4 => Some(Input::target(Targeting::Badges)),
+        _ => None,
+    }
+}
+
+// the keys that find their way around the menus
+fn menu_key(keycode: VirtualKeyCode) -> Option<app::Key> {
+    match keycode {
+        VirtualKeyCode::Up | VirtualKeyCode::W => Some(app::Key::Up),
+        VirtualKeyCode::Down | VirtualKeyCode::S => Some(app::Key::Down),
+        VirtualKeyCode::Left | VirtualKeyCode::A => Some(app::Key::Left),
+        VirtualKeyCode::Right | VirtualKeyCode::D => Some(app::Key::Right),
+        VirtualKeyCode::Return | VirtualKeyCode::Space => Some(app::Key::Confirm),
+        VirtualKeyCode::Escape | VirtualKeyCode::Back => Some(app::Key::Back),
+        _ => None,
+    }
+}
+
+// the game the command line asked for, recorded if it asked for that too
+fn command_line_session(options: &Options, args: &[String]) -> Result<Session> {
+    let session = start_session(options)?;
+    Ok(match (&options.record, options.seed) {
+        (Some(path), Some(seed)) => session.with_recorder(Recorder::create(path, args, seed)?),
+        _ => session,
+    })
+}
+
+// a new session with everything the frontend can show
+fn start_session(options: &Options) -> Result<Session> {
+    let session = new_session(options)?;
+    // text can't show the animations
+    Ok(
+        if options.animations && options.frontend != FrontendKind::Terminal {
+            session.with_animations(options.particles)
+        } else {
+            session
+        },
+    )
+}
+
+// a frame saved now and then, sized for however many boards there are
+async fn image_frontend(players: usize, options: &Options, skin: &Skin) -> frontend::Image {
+    let window = window_size(players);
+    let size = pixel_size(window, options.scale);
+    let renderer = capture::image_renderer(size, view_size(window), skin, options.cpu).await;
+    let output = options.output.as_deref().unwrap_or("frame.png");
+    frontend::Image::new(renderer, output.into())
+}
+
+// plays the session out in any frontend but the window
+fn run_frontend(mut session: Session, mut frontend: Box<dyn Frontend>) -> Result<()> {
+    let result = frontend::run(&mut session, frontend.as_mut());
+    // the terminal has to be back to normal before anything's printed
+    drop(frontend);
+    session.stop_recording();
+    session.disconnect();
+    result?;
+
+    println!("{}", session.status());
+    Ok(())
+}
+
+// the skin, changed however the options ask
+fn load_skin(options: &Options) -> Skin {
+    let mut skin = Skin::load_or_builtin(options.skin.as_deref());
+    if let Err(e) = skin.apply(&skin_overrides(options)) {
+        eprintln!("Skin options: {:#}", e);
+    }
+    skin
+}
+
+fn skin_overrides(options: &Options) -> skin::Overrides {
+    skin::Overrides {
+        background: options.background.clone(),
+        palette: options.palette,
+        patterns: options.patterns,
+        post: options.post.clone(),
+    }
+}
+
+// everyone playing or being watched, connected and seeded as the options say
+fn new_session(options: &Options) -> Result<Session> {
+    let seed = options.seed.unwrap_or_else(rand::random);
+    let connection = match &options.net {
+        Some(NetRole::Host(addr)) => Some(Connection::host(addr, seed)?),
+        Some(NetRole::Join(addr)) => Some(Connection::join(addr)?),
+        Some(NetRole::Server {
+            addr,
+            room,
+            capacity,
+        }) => Some(Connection::lobby(addr, room.as_deref(), *capacity)?),
+        None => None,
+    };
+    // everyone in a match plays the same piece sequence
+    let seed = connection
+        .as_ref()
+        .map_or(seed, |connection| connection.seed);
+
+    let width = 10;
+    let height = 40;
+
+    let delays = (
+        game::frames(options.line_clear_delay),
+        game::frames(options.are),
+    );
+    let new_game =
+        |board, seed| Game::new(board, options.mode, seed).with_delays(delays.0, delays.1);
+
+    let session = if let Some(addr) = &options.watch {
+        let connection = Connection::watch(addr)?;
+        let count = connection.players;
+
+        let players = layout_boards(count, width, height, options.grid)
+            .into_iter()
+            .enumerate()
+            .map(|(slot, board)| Player {
+                name: format!("Player {}", slot + 1),
+                game: Game::new(board, options.mode, 0),
+                controller: Controller::Remote(slot),
+            })
+            .collect();
+        Session::new(players, 0).with_connection(connection)
+    } else if let Mode::Versus { .. } = options.mode {
+        let count = connection
+            .as_ref()
+            .map_or(options.players, |connection| connection.players);
+        let mut boards = layout_boards(count, width, height, options.grid).into_iter();
+
+        let mut players = vec![Player {
+            name: String::from("You"),
+            game: new_game(boards.next().unwrap(), seed),
+            controller: Controller::Human,
+        }];
+
+        match &connection {
+            Some(connection) => {
+                let slots = (0..connection.players).filter(|&slot| slot != connection.slot);
+                for (slot, board) in slots.zip(boards) {
+                    players.push(Player {
+                        name: format!("Player {}", slot + 1),
+                        game: new_game(board, seed),
+                        controller: Controller::Remote(slot),
+                    });
+                }
+            }
+            None if count == 2 => players.push(Player {
+                name: String::from("AI"),
+                game: new_game(boards.next().unwrap(), seed),
+                controller: Controller::Ai(Ai::new(game::frames(options.ai_delay))),
+            }),
+            None => {
+                for (i, board) in boards.enumerate() {
+                    players.push(Player {
+                        name: format!("AI {}", i + 1),
+                        game: new_game(board, seed),
+                        controller: Controller::Ai(Ai::new(game::frames(options.ai_delay))),
+                    });
+                }
+            }
+        }
+
+        let mut session = Session::new(players, seed);
+        // spread the AIs over every strategy
+        for i in 1..session.players.len() {
+            if let Controller::Ai(_) = session.players[i].controller {
+                session.set_targeting(i, Targeting::ALL[i % Targeting::ALL.len()]);
+            }
+        }
+        // sent like any other input so online peers hear about it too
+        session.press(Input::target(options.targeting));
+
+        match (connection, options.rollback) {
+            (Some(connection), Some(input_delay)) => session
+                .with_connection(connection)
+                .with_rollback(input_delay),
+            (Some(connection), None) => session.with_connection(connection),
+            (None, _) => session,
+        }
+    } else {
+        let board = layout_boards(1, width, height, options.grid).remove(0);
+        Session::new(
+            vec![Player {
+                name: String::from("You"),
+                game: new_game(board, seed),
+                controller: Controller::Human,
+            }],
+            seed,
+        )
+    };
+
+    Ok(match &options.broadcast {
+        Some(addr) => {
+            let broadcaster = Broadcaster::bind(addr, session.players.len())?;
+            session.with_broadcaster(broadcaster)
+        }
+        None => session,
+    })
+}
+
+// world units from the left edge of the view to the right
+const VIEW_WIDTH: f32 = 1.6;
+
+fn window_size(count: usize) -> LogicalSize<f64> {
+    if count > 1 {
+        LogicalSize::new(1000.0, 700.0)
+    } else {
+        LogicalSize::new(500.0, 700.0)
+    }
+}
+
+// the layouts are all VIEW_WIDTH across, the window's shape decides the height
+fn view_size(size: LogicalSize<f64>) -> [f32; 2] {
+    [VIEW_WIDTH, VIEW_WIDTH * (size.height / size.width) as f32]
+}
+
+// `size` in pixels, times `scale`
+fn pixel_size(size: LogicalSize<f64>, scale: f32) -> PhysicalSize<u32> {
+    PhysicalSize::new(
+        (size.width * scale as f64).round().max(1.0) as u32,
+        (size.height * scale as f64).round().max(1.0) as u32,
+    )
+}
+
+// the first board is the one being played, the rest are opponents
+fn layout_boards(count: usize, width: usize, height: usize, grid: bool) -> Vec<Board> {
+    let layout = match count {
+        1 => {
+            let span = (10 + HUD_COLUMNS) as f32;
+            // the frame sticks out left of the board
+            let tile_size = (VIEW_WIDTH / (span + tiles::FRAME_WIDTH * 2.0)).min(20.0 / 200.0);
+            vec![(tile_size, span * tile_size / 2.0, centered(tile_size))]
+        }
+        2 => versus_layout(count),
+        _ => battle_layout(count),
+    };
+
+    layout
+        .into_iter()
+        .enumerate()
+        .map(|(i, (tile_size, offset_x, offset_y))| {
+            let mut board = Board::new(width, height, tile_size, offset_x, offset_y);
+            board.set_grid(grid);
+            // boards in the battle grid would run into the one above
+            if count > 2 && i > 0 {
+                board.set_buffer_hint(0);
+            }
+            board
+        })
+        .collect()
+}
+
+// the offset that puts the playfield, its frame and the buffer zone hint in the middle
+fn centered(tile_size: f32) -> f32 {
+    let rows = (board::VISIBLE_ROWS + board::BUFFER_HINT_ROWS) as f32;
+    tile_size * ((rows - tiles::FRAME_WIDTH) / 2.0)
+}
+
+// boards side by side, shrinking tiles to fit as more players join
+fn versus_layout(count: usize) -> Vec<(f32, f32, f32)> {
+    let stride = 10 + HUD_COLUMNS;
+    let span = (count * stride) as f32;
+    // room for the first board's frame, the others fit in the HUD before them
+    let tile_size = (VIEW_WIDTH / (span + tiles::FRAME_WIDTH)).min(5.0 / 100.0);
+    let left = -(span - tiles::FRAME_WIDTH) * tile_size / 2.0;
+
+    (0..count)
+        .map(|i| {
+            let x = left + (i * stride) as f32 * tile_size;
+            (tile_size, -x, centered(tile_size))
+        })
+        .collect()
+}
+
+// your board and HUD on the left, everyone else in a grid filling the rest of the window
+fn battle_layout(count: usize) -> Vec<(f32, f32, f32)> {
+    let tile_size = 5.0 / 100.0;
+    let half_width = VIEW_WIDTH / 2.0;
+    let frame = tiles::FRAME_WIDTH * tile_size;
+    let mut layout = vec![(tile_size, half_width - frame, centered(tile_size))];
+
+    let opponents = count - 1;
+    let grid_left = -half_width + frame + (10 + HUD_COLUMNS) as f32 * tile_size;
+    let (width, height) = (half_width - grid_left, 1.04);
+    let (columns, grid_tile_size) = (1..=opponents)
+        .map(|columns| {
+            let rows = opponents.div_ceil(columns);
+            let tile_size = (width / (columns * 11) as f32).min(height / (rows * 21) as f32);
+            (columns, tile_size)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap();
+    let grid_tile_size = grid_tile_size.min(tile_size);
+
+    for i in 0..opponents {
+        let x = grid_left + ((i % columns) * 11) as f32 * grid_tile_size;
+        let y = 0.54 - ((i / columns + 1) * 21) as f32 * grid_tile_size;
+        layout.push((grid_tile_size, -x, -y));
+    }
+    layout
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+pub const DEFAULT_ULTRA_TIME: Duration = Duration::from_secs(120);
+pub const MARATHON_MAX_LEVEL: u32 = 15;
+pub const MARATHON_LINES: u32 = 150;
+pub const DEFAULT_CHEESE_LINES: u32 = 18;
+pub const DEFAULT_CHEESE_MESSINESS: f32 = 1.0;
+pub const CHEESE_HEIGHT: u32 = 10;
+pub const DEFAULT_GARBAGE_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug)]
+pub enum Mode {
+    Free,
+    Ultra { time_limit: Duration },
+    Marathon { start_level: u32, endless: bool },
+    Cheese { lines: u32, messiness: f32 },
+    Versus { garbage_delay: Duration },
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Free => "Free Play",
+            Mode::Ultra { .. } => "Ultra",
+            Mode::Marathon { endless: false, .. } => "Marathon",
+            Mode::Marathon { endless: true, .. } => "Endless Marathon",
+            Mode::Cheese { .. } => "Cheese Race",
+            Mode::Versus { .. } => "Versus",
+        }
+    }
+
+    pub fn time_limit(&self) -> Option<Duration> {
+        match self {
+            Mode::Ultra { time_limit } => Some(*time_limit),
+            _ => None,
+        }
+    }
+
+    pub fn goal_reached(&self, lines: u32, garbage_cleared: u32) -> bool {
+        match self {
+            Mode::Cheese { lines: goal, .. } => garbage_cleared >= *goal,
+            Mode::Marathon {
+                start_level,
+                endless: false,
+            } => start_level + lines / 10 > MARATHON_MAX_LEVEL || lines >= MARATHON_LINES,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,555 @@
+//! Versus over TCP, either directly between two games or through the lobby
+//! server in `server.rs`.
+//!
+//! Every message is a single line of ASCII text, fields separated by spaces:
+//!
+//! | Message                                | Meaning                                                |
+//! |----------------------------------------|--------------------------------------------------------|
+//! | `HELLO <version>`                      | First line sent by both sides, versions must match     |
+//! | `LIST`                                 | Client asks the server for its rooms                   |
+//! | `ROOMS [<name>:<players>/<capacity>]*` | Server's room list, started rooms end in `*`           |
+//! | `ENTER <room> <capacity>`              | Client joins a room, creating it if it doesn't exist   |
+//! | `QUICK`                                | Client joins any waiting room or opens a 2-player one  |
+//! | `JOINED <room> <players> <capacity>`   | Server confirms a room and reports how full it is      |
+//! | `START <seed> <players> <slot>`        | The match begins, everyone plays the same `<seed>`     |
+//! | `GARBAGE <lines>`                      | Attack left over after cancelling incoming garbage     |
+//! | `BOARD <width> <height> <cells>`       | The sender's field, see below                          |
+//! | `TOPOUT`                               | The sender topped out and lost                         |
+//! | `INPUT <frame> <keys>`                 | Keys pressed on a frame in rollback mode, see below    |
+//! | `FROM <slot> <message>`                | Server relaying any of the match messages or `BYE`     |
+//! | `WINNER <slot>`                        | Server announcing the last player standing             |
+//! | `ERROR <text>`                         | Server rejected the last request                       |
+//! | `WATCH <players>`                      | A broadcast of `<players>` boards starts, see below    |
+//! | `STATUS <text>`                        | Broadcast score line to show spectators                |
+//! | `BYE`                                  | The sender is leaving, the connection closes           |
+//!
+//! `<cells>` is `width * height` characters, row by row from the bottom, using
+//! `.` for empty, `G` for garbage and the piece letter (`IJLOSTZ`) for blocks.
+//! The falling piece is drawn into the field.
+//!
+//! With `--rollback` every player runs everyone's game from the shared seed
+//! and only `INPUT` is exchanged, `<keys>` being the bits of `input::Input`.
+//! Each side sends its first frames of input delay as empty inputs so nobody
+//! has to know the others' delay. All players in a match need `--rollback`.
+//!
+//! Directly connected, the joining side sends `HELLO` first and the host
+//! answers with `HELLO` and `START <seed> 2 1`; the host itself is slot 0.
+//! Through the server, clients say `HELLO`, then `ENTER` or `QUICK`, and the
+//! server sends `START` to everyone once the room is full. Garbage sent to the
+//! server goes to one opponent picked at random, everything else is relayed to
+//! the whole room. Targeting strategies only apply with `--rollback`, where
+//! every peer routes garbage itself.
+//!
+//! A game started with `--broadcast` also listens for spectators. After the
+//! `HELLO` exchange it sends `WATCH`, then the latest `FROM <slot> BOARD` of
+//! every board and the latest `STATUS`, so late joiners start in sync. From then
+//! on it sends whichever of those changed each frame. Spectators never talk.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, TryRecvError},
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use super::input::Input;
+use super::tetrimino::Color;
+
+pub const PROTOCOL_VERSION: u32 = 6;
+// biggest board side a peer may send, far past anything the game plays on
+const MAX_BOARD_SIDE: usize = 64;
+// well past the longest message, a full BOARD or a busy room list
+const MAX_LINE: u64 = 64 * 1024;
+
+#[derive(Clone, Debug)]
+pub enum NetRole {
+    Host(String),
+    Join(String),
+    Server {
+        addr: String,
+        room: Option<String>,
+        capacity: usize,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct RoomInfo {
+    pub name: String,
+    pub players: usize,
+    pub capacity: usize,
+    pub started: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum Message {
+    Hello {
+        version: u32,
+    },
+    List,
+    Rooms(Vec<RoomInfo>),
+    Enter {
+        room: String,
+        capacity: usize,
+    },
+    Quick,
+    Joined {
+        room: String,
+        players: usize,
+        capacity: usize,
+    },
+    Start {
+        seed: u64,
+        players: usize,
+        slot: usize,
+    },
+    Garbage(u32),
+    Board {
+        width: usize,
+        height: usize,
+        cells: Vec<Color>,
+    },
+    ToppedOut,
+    Input {
+        frame: u32,
+        input: Input,
+    },
+    From {
+        slot: usize,
+        message: Box<Message>,
+    },
+    Winner(usize),
+    Error(String),
+    Watch {
+        players: usize,
+    },
+    Status(String),
+    Bye,
+}
+
+impl Message {
+    pub fn encode(&self) -> String {
+        match self {
+            Message::Hello { version } => format!("HELLO {}", version),
+            Message::List => String::from("LIST"),
+            Message::Rooms(rooms) => {
+                let mut line = String::from("ROOMS");
+                for room in rooms {
+                    line.push_str(&format!(
+                        " {}:{}/{}",
+                        room.name, room.players, room.capacity
+                    ));
+                    if room.started {
+                        line.push('*');
+                    }
+                }
+                line
+            }
+            Message::Enter { room, capacity } => format!("ENTER {} {}", room, capacity),
+            Message::Quick => String::from("QUICK"),
+            Message::Joined {
+                room,
+                players,
+                capacity,
+            } => format!("JOINED {} {} {}", room, players, capacity),
+            Message::Start {
+                seed,
+                players,
+                slot,
+            } => format!("START {} {} {}", seed, players, slot),
+            Message::Garbage(lines) => format!("GARBAGE {}", lines),
+            Message::Board {
+                width,
+                height,
+                cells,
+            } => {
+                let cells: String = cells.iter().map(|color| color.to_char()).collect();
+                format!("BOARD {} {} {}", width, height, cells)
+            }
+            Message::ToppedOut => String::from("TOPOUT"),
+            Message::Input { frame, input } => format!("INPUT {} {}", frame, input.0),
+            Message::From { slot, message } => format!("FROM {} {}", slot, message.encode()),
+            Message::Winner(slot) => format!("WINNER {}", slot),
+            Message::Error(text) => format!("ERROR {}", text),
+            Message::Watch { players } => format!("WATCH {}", players),
+            Message::Status(text) => format!("STATUS {}", text),
+            Message::Bye => String::from("BYE"),
+        }
+    }
+
+    pub fn decode(line: &str) -> Result<Self> {
+        let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let mut fields = rest.split_whitespace();
+        let mut field = || {
+            fields
+                .next()
+                .ok_or_else(|| anyhow!("Missing field in {} message", kind))
+        };
+
+        let message = match kind {
+            "HELLO" => Message::Hello {
+                version: field()?.parse()?,
+            },
+            "LIST" => Message::List,
+            "ROOMS" => Message::Rooms(
+                rest.split_whitespace()
+                    .map(decode_room)
+                    .collect::<Result<_>>()?,
+            ),
+            "ENTER" => Message::Enter {
+                room: valid_room_name(field()?)?,
+                capacity: field()?.parse()?,
+            },
+            "QUICK" => Message::Quick,
+            "JOINED" => Message::Joined {
+                room: field()?.to_string(),
+                players: field()?.parse()?,
+                capacity: field()?.parse()?,
+            },
+            "START" => Message::Start {
+                seed: field()?.parse()?,
+                players: field()?.parse()?,
+                slot: field()?.parse()?,
+            },
+            "GARBAGE" => Message::Garbage(field()?.parse()?),
+            "BOARD" => {
+                let width: usize = field()?.parse()?;
+                let height: usize = field()?.parse()?;
+                if width > MAX_BOARD_SIDE || height > MAX_BOARD_SIDE {
+                    bail!("Board of {}x{} is too big", width, height);
+                }
+                let expected = width
+                    .checked_mul(height)
+                    .ok_or_else(|| anyhow!("Board of {}x{} is too big", width, height))?;
+                let cells = field()?
+                    .chars()
+                    .map(|c| Color::from_char(c).ok_or_else(|| anyhow!("Bad cell: {}", c)))
+                    .collect::<Result<Vec<_>>>()?;
+                if cells.len() != expected {
+                    bail!("Board has {} cells, expected {}", cells.len(), expected);
+                }
+                Message::Board {
+                    width,
+                    height,
+                    cells,
+                }
+            }
+            "TOPOUT" => Message::ToppedOut,
+            "INPUT" => Message::Input {
+                frame: field()?.parse()?,
+                input: Input(field()?.parse()?),
+            },
+            "FROM" => {
+                let (slot, message) = rest
+                    .split_once(' ')
+                    .ok_or_else(|| anyhow!("Missing field in FROM message"))?;
+                Message::From {
+                    slot: slot.parse()?,
+                    message: Box::new(Message::decode(message)?),
+                }
+            }
+            "WINNER" => Message::Winner(field()?.parse()?),
+            "ERROR" => Message::Error(rest.to_string()),
+            "WATCH" => Message::Watch {
+                players: field()?.parse()?,
+            },
+            "STATUS" => Message::Status(rest.to_string()),
+            "BYE" => Message::Bye,
+            _ => bail!("Unknown message: {}", kind),
+        };
+
+        Ok(message)
+    }
+}
+
+fn decode_room(room: &str) -> Result<RoomInfo> {
+    let bad_room = || anyhow!("Bad room: {}", room);
+
+    let (name, counts) = room.split_once(':').ok_or_else(bad_room)?;
+    let started = counts.ends_with('*');
+    let (players, capacity) = counts
+        .trim_end_matches('*')
+        .split_once('/')
+        .ok_or_else(bad_room)?;
+
+    Ok(RoomInfo {
+        name: name.to_string(),
+        players: players.parse()?,
+        capacity: capacity.parse()?,
+        started,
+    })
+}
+
+pub fn valid_room_name(name: &str) -> Result<String> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("Room names may only use letters, digits, '-' and '_'");
+    }
+    Ok(name.to_string())
+}
+
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Receiver<Message>,
+    closed: bool,
+
+    pub seed: u64,
+    pub players: usize,
+    pub slot: usize,
+}
+
+impl Connection {
+    pub fn host(addr: &str, seed: u64) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        println!("Waiting for an opponent on {}", listener.local_addr()?);
+
+        let (mut stream, peer) = listener.accept()?;
+        println!("{} joined", peer);
+
+        let mut reader = BufReader::new(stream.try_clone()?);
+        expect_hello(&mut reader)?;
+
+        write_message(
+            &mut stream,
+            &Message::Hello {
+                version: PROTOCOL_VERSION,
+            },
+        )?;
+        write_message(
+            &mut stream,
+            &Message::Start {
+                seed,
+                players: 2,
+                slot: 1,
+            },
+        )?;
+
+        Self::start(stream, reader, seed, 2, 0)
+    }
+
+    pub fn join(addr: &str) -> Result<Self> {
+        let (stream, mut reader) = connect(addr)?;
+        let (seed, players, slot) = wait_for_start(&mut reader)?;
+        Self::start(stream, reader, seed, players, slot)
+    }
+
+    pub fn lobby(addr: &str, room: Option<&str>, capacity: usize) -> Result<Self> {
+        let (mut stream, mut reader) = connect(addr)?;
+
+        let request = match room {
+            Some(room) => Message::Enter {
+                room: valid_room_name(room)?,
+                capacity,
+            },
+            None => Message::Quick,
+        };
+        write_message(&mut stream, &request)?;
+
+        let (seed, players, slot) = wait_for_start(&mut reader)?;
+        Self::start(stream, reader, seed, players, slot)
+    }
+
+    pub fn watch(addr: &str) -> Result<Self> {
+        let (stream, mut reader) = connect(addr)?;
+        let players = match read_message(&mut reader)? {
+            Message::Watch { players } => players,
+            message => bail!("Expected WATCH, got {:?}", message),
+        };
+        // spectators have no board, so their slot is past the last player's
+        Self::start(stream, reader, 0, players, players)
+    }
+
+    pub fn list_rooms(addr: &str) -> Result<Vec<RoomInfo>> {
+        let (mut stream, mut reader) = connect(addr)?;
+        write_message(&mut stream, &Message::List)?;
+
+        match read_message(&mut reader)? {
+            Message::Rooms(rooms) => {
+                let _ = write_message(&mut stream, &Message::Bye);
+                Ok(rooms)
+            }
+            message => bail!("Expected ROOMS, got {:?}", message),
+        }
+    }
+
+    fn start(
+        stream: TcpStream,
+        mut reader: BufReader<TcpStream>,
+        seed: u64,
+        players: usize,
+        slot: usize,
+    ) -> Result<Self> {
+        stream.set_nodelay(true)?;
+
+        let (sender, incoming) = mpsc::channel();
+        std::thread::spawn(move || {
+            while let Ok(message) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            stream,
+            incoming,
+            closed: false,
+            seed,
+            players,
+            slot,
+        })
+    }
+
+    pub fn send(&mut self, message: &Message) -> Result<()> {
+        write_message(&mut self.stream, message)
+    }
+
+    // a dropped connection shows up as a single BYE
+    pub fn poll(&mut self) -> Vec<Message> {
+        let mut messages = Vec::new();
+        loop {
+            match self.incoming.try_recv() {
+                Ok(message) => messages.push(message),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.closed {
+                        messages.push(Message::Bye);
+                        self.closed = true;
+                    }
+                    break;
+                }
+            }
+        }
+        messages
+    }
+}
+
+fn connect(addr: &str) -> Result<(TcpStream, BufReader<TcpStream>)> {
+    let mut stream = TcpStream::connect(addr)?;
+    println!("Connected to {}", addr);
+
+    write_message(
+        &mut stream,
+        &Message::Hello {
+            version: PROTOCOL_VERSION,
+        },
+    )?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    expect_hello(&mut reader)?;
+    Ok((stream, reader))
+}
+
+fn wait_for_start(reader: &mut BufReader<TcpStream>) -> Result<(u64, usize, usize)> {
+    loop {
+        match read_message(reader)? {
+            Message::Start {
+                seed,
+                players,
+                slot,
+            } => return Ok((seed, players, slot)),
+            Message::Joined {
+                room,
+                players,
+                capacity,
+            } => println!("In room {} ({}/{} players)", room, players, capacity),
+            Message::Error(text) => bail!("Server error: {}", text),
+            message => bail!("Expected START, got {:?}", message),
+        }
+    }
+}
+
+pub fn write_message(stream: &mut TcpStream, message: &Message) -> Result<()> {
+    writeln!(stream, "{}", message.encode())?;
+    Ok(())
+}
+
+pub fn read_message(reader: &mut impl BufRead) -> Result<Message> {
+    let mut line = String::new();
+    let read = reader.take(MAX_LINE).read_line(&mut line)?;
+    if read == 0 {
+        bail!("Connection closed");
+    }
+    // a peer that never ends its line would have us buffer it forever
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        bail!("Message longer than {} bytes", MAX_LINE);
+    }
+    Message::decode(line.trim_end())
+}
+
+pub fn expect_hello(reader: &mut impl BufRead) -> Result<()> {
+    match read_message(reader)? {
+        Message::Hello { version } if version == PROTOCOL_VERSION => Ok(()),
+        Message::Hello { version } => bail!(
+            "Protocol version mismatch: ours is {}, theirs is {}",
+            PROTOCOL_VERSION,
+            version
+        ),
+        message => bail!("Expected HELLO, got {:?}", message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn board_round_trips() {
+        let cells = vec![
+            Color::Empty,
+            Color::LightBlue,
+            Color::Garbage,
+            Color::Purple,
+        ];
+        let message = Message::Board {
+            width: 2,
+            height: 2,
+            cells,
+        };
+        let line = message.encode();
+        assert_eq!(line, "BOARD 2 2 .IGT");
+
+        let decoded = Message::decode(&line).unwrap();
+        assert!(
+            matches!(decoded, Message::Board { width: 2, height: 2, ref cells } if cells.len() == 4)
+        );
+        assert_eq!(decoded.encode(), line);
+    }
+
+    #[test]
+    fn relayed_messages_round_trip() {
+        let message = Message::From {
+            slot: 3,
+            message: Box::new(Message::Input {
+                frame: 120,
+                input: Input(0x41),
+            }),
+        };
+        let line = message.encode();
+        assert_eq!(Message::decode(&line).unwrap().encode(), line);
+    }
+
+    #[test]
+    fn hostile_board_sizes_are_rejected() {
+        // would overflow `width * height`
+        let huge = format!("BOARD {} {} .", usize::MAX, usize::MAX);
+        assert!(Message::decode(&huge).is_err());
+        assert!(Message::decode("BOARD 65 1 .").is_err());
+        assert!(Message::decode("BOARD 2 2 ...").is_err());
+    }
+
+    #[test]
+    fn endless_lines_are_rejected() {
+        let endless = vec![b'A'; MAX_LINE as usize * 2];
+        assert!(read_message(&mut &endless[..]).is_err());
+
+        let line = format!("{}\n", Message::Bye.encode());
+        assert!(matches!(
+            read_message(&mut line.as_bytes()),
+            Ok(Message::Bye)
+        ));
+    }
+}
@@ -0,0 +1,343 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+
+use super::battle::Targeting;
+use super::frontend::FrontendKind;
+use super::mode::{self, Mode};
+use super::net::NetRole;
+use super::palette::Palette;
+use super::rollback::MAX_INPUT_DELAY;
+use super::server::MAX_PLAYERS;
+use super::skin;
+
+const DEFAULT_AI_DELAY: Duration = Duration::from_millis(150);
+const DEFAULT_INPUT_DELAY: u32 = 2;
+const MAX_PARTICLES: f32 = 10.0;
+// long enough for the clear animation to play out
+const DEFAULT_LINE_CLEAR_DELAY: Duration = Duration::from_millis(250);
+const DEFAULT_CLIP_FPS: u32 = 30;
+const MAX_CLIP_FPS: u32 = 60;
+// of the window's size
+const DEFAULT_CLIP_SCALE: f32 = 0.5;
+const MAX_CLIP_SCALE: f32 = 4.0;
+
+pub struct Options {
+    pub mode: Mode,
+    // the command line picked a game, so the window skips the title screen
+    pub play_now: bool,
+    pub ai_delay: Duration,
+    pub net: Option<NetRole>,
+    pub seed: Option<u64>,
+    pub list_rooms: bool,
+    // boards in a local match, or room size on a server
+    pub players: usize,
+    pub targeting: Targeting,
+    // input delay in frames when playing online with rollback
+    pub rollback: Option<u32>,
+    pub broadcast: Option<String>,
+    pub watch: Option<String>,
+    pub animations: bool,
+    // how many particles bursts throw, 1 being the default and 0 none at all
+    pub particles: f32,
+    pub line_clear_delay: Duration,
+    // entry delay before each new piece
+    pub are: Duration,
+    // directory with a skin manifest and atlas
+    pub skin: Option<String>,
+    // lines between the cells of each board
+    pub grid: bool,
+    // a color or an image path, in place of the skin's background
+    pub background: Option<String>,
+    pub palette: Palette,
+    // marks on the tiles telling the pieces apart
+    pub patterns: bool,
+    // post-processing effects in place of the skin's
+    pub post: Option<Vec<String>>,
+    // reload shaders and the skin when their files change
+    pub dev: bool,
+    // a saved board or fumen to draw to `output` instead of playing
+    pub render: Option<String>,
+    // a replay to turn into a clip at `output` instead of playing
+    pub replay: Option<String>,
+    pub output: Option<String>,
+    // where to save a replay of this game
+    pub record: Option<String>,
+    pub fps: u32,
+    pub scale: f32,
+    // game frames of the replay to put in the clip, up to the end when there's no second
+    pub frames: (u32, Option<u32>),
+    // draw `render` images on the CPU rather than through wgpu
+    pub cpu: bool,
+    // how the game is shown and played
+    pub frontend: FrontendKind,
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut mode_name = String::from("free");
+        let mut time_limit = mode::DEFAULT_ULTRA_TIME;
+        let mut start_level = 1;
+        let mut endless = false;
+        let mut garbage_lines = mode::DEFAULT_CHEESE_LINES;
+        let mut messiness = mode::DEFAULT_CHEESE_MESSINESS;
+        let mut garbage_delay = mode::DEFAULT_GARBAGE_DELAY;
+        let mut ai_delay = DEFAULT_AI_DELAY;
+        let mut net = None;
+        let mut server = None;
+        let mut room = None;
+        let mut players = 2;
+        let mut targeting = Targeting::Random;
+        let mut seed = None;
+        let mut list_rooms = false;
+        let mut rollback = false;
+        let mut input_delay = DEFAULT_INPUT_DELAY;
+        let mut broadcast = None;
+        let mut watch = None;
+        let mut animations = true;
+        let mut particles = 1.0;
+        let mut line_clear_delay = None;
+        let mut are = None;
+        let mut skin = None;
+        let mut grid = false;
+        let mut background = None;
+        let mut palette = Palette::Skin;
+        let mut patterns = false;
+        let mut post = None;
+        let mut dev = false;
+        let mut render = None;
+        let mut replay = None;
+        let mut output = None;
+        let mut record = None;
+        let mut fps = DEFAULT_CLIP_FPS;
+        let mut scale = DEFAULT_CLIP_SCALE;
+        let mut frames = (0, None);
+        let mut cpu = false;
+        let mut frontend = FrontendKind::Window;
+        let mut play_now = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--mode" => {
+                    mode_name = next_value(&mut args, &arg)?;
+                    play_now = true;
+                }
+                "--time" => {
+                    let secs: u64 = next_value(&mut args, &arg)?.parse()?;
+                    time_limit = Duration::from_secs(secs);
+                }
+                "--level" => {
+                    start_level = next_value(&mut args, &arg)?.parse()?;
+                    if !(1..=mode::MARATHON_MAX_LEVEL).contains(&start_level) {
+                        bail!("Level must be between 1 and {}", mode::MARATHON_MAX_LEVEL);
+                    }
+                }
+                "--endless" => endless = true,
+                "--host" => net = Some(NetRole::Host(next_value(&mut args, &arg)?)),
+                "--join" => net = Some(NetRole::Join(next_value(&mut args, &arg)?)),
+                "--server" => server = Some(next_value(&mut args, &arg)?),
+                "--room" => room = Some(next_value(&mut args, &arg)?),
+                "--players" => {
+                    players = next_value(&mut args, &arg)?.parse()?;
+                    if !(2..=MAX_PLAYERS).contains(&players) {
+                        bail!("Matches hold between 2 and {} players", MAX_PLAYERS);
+                    }
+                }
+                "--targeting" => {
+                    let name = next_value(&mut args, &arg)?;
+                    targeting = Targeting::parse(&name)
+                        .ok_or_else(|| anyhow!("Unknown targeting: {}", name))?;
+                }
+                "--list-rooms" => list_rooms = true,
+                "--rollback" => rollback = true,
+                "--input-delay" => {
+                    input_delay = next_value(&mut args, &arg)?.parse()?;
+                    if input_delay > MAX_INPUT_DELAY {
+                        bail!("Input delay must be at most {} frames", MAX_INPUT_DELAY);
+                    }
+                }
+                "--broadcast" => broadcast = Some(next_value(&mut args, &arg)?),
+                "--watch" => watch = Some(next_value(&mut args, &arg)?),
+                "--no-animations" => animations = false,
+                "--particles" => {
+                    particles = next_value(&mut args, &arg)?.parse()?;
+                    if !(0.0..=MAX_PARTICLES).contains(&particles) {
+                        bail!("Particles must be between 0 and {}", MAX_PARTICLES);
+                    }
+                }
+                "--line-clear-delay" => {
+                    let millis: u64 = next_value(&mut args, &arg)?.parse()?;
+                    line_clear_delay = Some(Duration::from_millis(millis));
+                }
+                "--are" => {
+                    let millis: u64 = next_value(&mut args, &arg)?.parse()?;
+                    are = Some(Duration::from_millis(millis));
+                }
+                "--skin" => skin = Some(next_value(&mut args, &arg)?),
+                "--grid" => grid = true,
+                "--background" => background = Some(next_value(&mut args, &arg)?),
+                "--palette" => {
+                    let name = next_value(&mut args, &arg)?;
+                    palette = Palette::parse(&name)
+                        .ok_or_else(|| anyhow!("Unknown palette: {}", name))?;
+                }
+                "--patterns" => patterns = true,
+                "--post" => post = Some(skin::parse_effects(&next_value(&mut args, &arg)?)),
+                "--dev" => dev = true,
+                "--render" => render = Some(next_value(&mut args, &arg)?),
+                "--replay" => replay = Some(next_value(&mut args, &arg)?),
+                "--output" => output = Some(next_value(&mut args, &arg)?),
+                "--record" => record = Some(next_value(&mut args, &arg)?),
+                "--fps" => {
+                    fps = next_value(&mut args, &arg)?.parse()?;
+                    if !(1..=MAX_CLIP_FPS).contains(&fps) {
+                        bail!("Clips run at between 1 and {} fps", MAX_CLIP_FPS);
+                    }
+                }
+                "--scale" => {
+                    scale = next_value(&mut args, &arg)?.parse()?;
+                    if !(scale > 0.0 && scale <= MAX_CLIP_SCALE) {
+                        bail!("Scale must be above 0 and at most {}", MAX_CLIP_SCALE);
+                    }
+                }
+                "--frames" => frames = parse_range(&next_value(&mut args, &arg)?)?,
+                "--cpu" => cpu = true,
+                "--frontend" => {
+                    let name = next_value(&mut args, &arg)?;
+                    frontend = FrontendKind::parse(&name)
+                        .ok_or_else(|| anyhow!("Unknown frontend: {}", name))?;
+                }
+                "--tui" => frontend = FrontendKind::Terminal,
+                "--seed" => seed = Some(next_value(&mut args, &arg)?.parse()?),
+                "--garbage-delay" => {
+                    let millis: u64 = next_value(&mut args, &arg)?.parse()?;
+                    garbage_delay = Duration::from_millis(millis);
+                }
+                "--ai-delay" => {
+                    let millis: u64 = next_value(&mut args, &arg)?.parse()?;
+                    ai_delay = Duration::from_millis(millis);
+                }
+                "--garbage" => {
+                    garbage_lines = next_value(&mut args, &arg)?.parse()?;
+                    if garbage_lines == 0 {
+                        bail!("A cheese race needs at least one line of garbage");
+                    }
+                }
+                "--messiness" => {
+                    messiness = next_value(&mut args, &arg)?.parse()?;
+                    if !(0.0..=1.0).contains(&messiness) {
+                        bail!("Messiness must be between 0 and 1");
+                    }
+                }
+                _ => bail!("Unknown argument: {}", arg),
+            }
+        }
+
+        if let Some(addr) = server {
+            net = Some(NetRole::Server {
+                addr,
+                room,
+                capacity: players,
+            });
+        }
+
+        if watch.is_some() && (net.is_some() || broadcast.is_some()) {
+            bail!("--watch can't be combined with playing or broadcasting");
+        }
+        // replays are played back from one player's inputs alone
+        if record.is_some() && (net.is_some() || broadcast.is_some() || watch.is_some()) {
+            bail!("--record only works for local games");
+        }
+
+        // without animations there's nothing to wait for, unless asked to
+        let default_delay = if animations {
+            DEFAULT_LINE_CLEAR_DELAY
+        } else {
+            Duration::ZERO
+        };
+        let mut line_clear_delay = line_clear_delay.unwrap_or(default_delay);
+        let mut are = are.unwrap_or(Duration::ZERO);
+
+        // joining or watching someone else's game can't wait on a menu
+        play_now |= net.is_some() || watch.is_some() || record.is_some();
+
+        // playing over the network is always a versus match
+        if net.is_some() {
+            mode_name = String::from("versus");
+            // every peer has to step through the same frames
+            line_clear_delay = Duration::ZERO;
+            are = Duration::ZERO;
+        } else if rollback {
+            bail!("--rollback needs --host, --join or --server");
+        }
+
+        let mode = match mode_name.as_str() {
+            "free" => Mode::Free,
+            "ultra" => Mode::Ultra { time_limit },
+            "marathon" => Mode::Marathon {
+                start_level,
+                endless,
+            },
+            "cheese" => Mode::Cheese {
+                lines: garbage_lines,
+                messiness,
+            },
+            "versus" => Mode::Versus { garbage_delay },
+            _ => bail!("Unknown mode: {}", mode_name),
+        };
+
+        Ok(Self {
+            mode,
+            play_now,
+            ai_delay,
+            net,
+            seed,
+            list_rooms,
+            players,
+            targeting,
+            rollback: rollback.then_some(input_delay),
+            broadcast,
+            watch,
+            animations,
+            particles,
+            line_clear_delay,
+            are,
+            skin,
+            grid,
+            background,
+            palette,
+            patterns,
+            post,
+            dev,
+            render,
+            replay,
+            output,
+            record,
+            fps,
+            scale,
+            frames,
+            cpu,
+            frontend,
+        })
+    }
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow!("Missing value for {}", flag))
+}
+
+// `start..end` with either side left out
+fn parse_range(value: &str) -> Result<(u32, Option<u32>)> {
+    let (start, end) = value
+        .split_once("..")
+        .ok_or_else(|| anyhow!("Frame ranges look like 600..1200"))?;
+    let start = if start.is_empty() { 0 } else { start.parse()? };
+    let end = if end.is_empty() {
+        None
+    } else {
+        Some(end.parse()?)
+    };
+    Ok((start, end))
+}
@@ -0,0 +1,100 @@
+// piece colors that stay apart for players who see color differently
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Palette {
+    // whatever the skin's atlas has
+    #[default]
+    Skin,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+    HighContrast,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 5] = [
+        Self::Skin,
+        Self::Deuteranopia,
+        Self::Protanopia,
+        Self::Tritanopia,
+        Self::HighContrast,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "skin" | "default" => Some(Self::Skin),
+            "deuteranopia" => Some(Self::Deuteranopia),
+            "protanopia" => Some(Self::Protanopia),
+            "tritanopia" => Some(Self::Tritanopia),
+            "high-contrast" => Some(Self::HighContrast),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Skin => "skin",
+            Self::Deuteranopia => "deuteranopia",
+            Self::Protanopia => "protanopia",
+            Self::Tritanopia => "tritanopia",
+            Self::HighContrast => "high-contrast",
+        }
+    }
+
+    // one color for each tile `tiles::cell` hands out, up to and including garbage
+    pub fn colors(self) -> Option<[[u8; 3]; 8]> {
+        match self {
+            Self::Skin => None,
+            Self::Deuteranopia => Some(DEUTERANOPIA),
+            Self::Protanopia => Some(PROTANOPIA),
+            Self::Tritanopia => Some(TRITANOPIA),
+            Self::HighContrast => Some(HIGH_CONTRAST),
+        }
+    }
+}
+
+// mostly told apart along blue and yellow, the Okabe-Ito colors
+const DEUTERANOPIA: [[u8; 3]; 8] = [
+    [0x00, 0x72, 0xb2], // J
+    [0xd5, 0x5e, 0x00], // Z
+    [0x00, 0x9e, 0x73], // S
+    [0xe6, 0x9f, 0x00], // L
+    [0xcc, 0x79, 0xa7], // T
+    [0xf0, 0xe4, 0x42], // O
+    [0x56, 0xb4, 0xe9], // I
+    [0x8c, 0x8c, 0x8c], // garbage
+];
+
+// reds look dark, so the red piece is kept bright and pink
+const PROTANOPIA: [[u8; 3]; 8] = [
+    [0x1f, 0x5f, 0xbf], // J
+    [0xff, 0x8c, 0xc6], // Z
+    [0x00, 0xa8, 0x8a], // S
+    [0xfe, 0x61, 0x00], // L
+    [0x78, 0x5e, 0xf0], // T
+    [0xff, 0xe6, 0x40], // O
+    [0x9a, 0xd0, 0xf5], // I
+    [0x8c, 0x8c, 0x8c], // garbage
+];
+
+// blues and yellows run together, told apart along red and green instead
+const TRITANOPIA: [[u8; 3]; 8] = [
+    [0x1b, 0x3a, 0x8c], // J
+    [0xe8, 0x28, 0x2b], // Z
+    [0x1f, 0x9e, 0x60], // S
+    [0xf0, 0x80, 0x30], // L
+    [0xb0, 0x1c, 0x7a], // T
+    [0xf6, 0xf0, 0xd8], // O
+    [0x5f, 0xd3, 0xe6], // I
+    [0x8c, 0x8c, 0x8c], // garbage
+];
+
+const HIGH_CONTRAST: [[u8; 3]; 8] = [
+    [0x00, 0x40, 0xff], // J
+    [0xff, 0x00, 0x00], // Z
+    [0x00, 0xff, 0x00], // S
+    [0xff, 0x80, 0x00], // L
+    [0xff, 0x00, 0xff], // T
+    [0xff, 0xff, 0x00], // O
+    [0x00, 0xff, 0xff], // I
+    [0xb0, 0xb0, 0xb0], // garbage
+];
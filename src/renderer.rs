@@ -1,17 +1,120 @@
 pub mod data;
 mod init;
+mod instances;
+pub mod particles;
+mod post;
+mod reload;
 mod render;
+mod software;
+pub mod text;
 mod texture;
+pub mod tiles;
+
+use anyhow::Result;
+
+use data::Instance;
+pub use post::check_effect;
+pub use software::SoftwareRenderer;
+
+// anything that can draw a frame of tiles, with text and effects over them, into an image
+pub trait ImageRenderer {
+    fn render_to_image(
+        &mut self,
+        tiles: &[Instance],
+        overlay: &[Instance],
+    ) -> Result<image::RgbaImage>;
+}
+
 pub struct Renderer {
-    pub surface: wgpu::Surface,
+    // none when rendering headless
+    pub surface: Option<wgpu::Surface>,
+    #[allow(dead_code)]
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub size: winit::dpi::PhysicalSize<u32>,
+    pub config: wgpu::SurfaceConfiguration,
+    // the area in world units that always stays visible
+    pub view: [f32; 2],
+    pub scale_buffer: wgpu::Buffer,
+    // columns and rows of the tile atlas
+    pub atlas_buffer: wgpu::Buffer,
+    pub bind_layout: wgpu::BindGroupLayout,
 
+    pub pipeline_layout: wgpu::PipelineLayout,
     pub pipeline: wgpu::RenderPipeline,
 
-    pub vertex_buffer: wgpu::Buffer,
+    pub quad_buffer: wgpu::Buffer,
+    pub tiles: instances::InstanceBuffer,
+    // text and effects, drawn over the tiles from the font atlas
+    pub overlay: instances::InstanceBuffer,
 
     pub bind_group: wgpu::BindGroup,
+    pub overlay_bind_group: wgpu::BindGroup,
+    // the skin's background image, drawn over the whole window before anything else
+    pub backdrop_bind_group: Option<wgpu::BindGroup>,
+    // a single cell, the image is the whole texture
+    pub backdrop_buffer: wgpu::Buffer,
+    pub backdrop: instances::InstanceBuffer,
+
+    hot_reload: Option<reload::HotReload>,
+    // none when the skin has no post-processing, then frames go straight to the screen
+    post: Option<post::PostChain>,
+
+    // the skin's atlas cell for each cell the tiles ask for
+    pub cells: Vec<Option<u32>>,
+    pub patterns: bool,
+    remapped: Vec<Instance>,
+    pub background: wgpu::Color,
+}
+
+impl Renderer {
+    pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        // minimised windows report a zero size, which can't be configured
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+
+        self.size = size;
+        self.config.width = size.width;
+        self.config.height = size.height;
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.config);
+        }
+        if let Some(post) = &mut self.post {
+            post.resize(&self.device, &self.queue, size);
+        }
+
+        self.queue.write_buffer(
+            &self.scale_buffer,
+            0,
+            bytemuck::cast_slice(&fit_scale(size, self.view)),
+        );
+    }
+}
+
+// the largest scale that fits `view` in the window with square pixels, centred
+fn fit_scale(size: winit::dpi::PhysicalSize<u32>, view: [f32; 2]) -> [f32; 2] {
+    let (width, height) = (size.width.max(1) as f32, size.height.max(1) as f32);
+    let pixels_per_unit = pixels_per_unit(size, view);
+    [
+        2.0 * pixels_per_unit / width,
+        2.0 * pixels_per_unit / height,
+    ]
+}
+
+// covers the whole window, wider or taller than the view as it may be
+fn backdrop_instance(size: winit::dpi::PhysicalSize<u32>, view: [f32; 2]) -> Instance {
+    let pixels_per_unit = pixels_per_unit(size, view);
+    let half_width = size.width.max(1) as f32 / pixels_per_unit / 2.0;
+    let half_height = size.height.max(1) as f32 / pixels_per_unit / 2.0;
+    Instance {
+        size: [half_width * 2.0, half_height * 2.0],
+        ..Instance::tile(-half_width, -half_height, 0.0, 0)
+    }
+}
+
+fn pixels_per_unit(size: winit::dpi::PhysicalSize<u32>, view: [f32; 2]) -> f32 {
+    let (width, height) = (size.width.max(1) as f32, size.height.max(1) as f32);
+    (width / view[0]).min(height / view[1])
 }
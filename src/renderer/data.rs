@@ -5,14 +5,57 @@ pub struct Vertex {
     pub uv: [f32; 2],
 }
 
-impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
-    pub fn get_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Vertex::ATTRIBS,
+// the unit quad every instance is stretched from, uv (0, 0) being the top left
+pub const QUAD: [Vertex; 6] = [
+    Vertex {
+        position: [0.0, 0.0, 0.0],
+        uv: [0.0, 1.0],
+    },
+    Vertex {
+        position: [1.0, 0.0, 0.0],
+        uv: [1.0, 1.0],
+    },
+    Vertex {
+        position: [1.0, 1.0, 0.0],
+        uv: [1.0, 0.0],
+    },
+    Vertex {
+        position: [0.0, 0.0, 0.0],
+        uv: [0.0, 1.0],
+    },
+    Vertex {
+        position: [0.0, 1.0, 0.0],
+        uv: [0.0, 0.0],
+    },
+    Vertex {
+        position: [1.0, 1.0, 0.0],
+        uv: [1.0, 0.0],
+    },
+];
+
+// one tile or glyph: where it goes and which atlas cell it shows, whatever draws it
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Instance {
+    // bottom left corner
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    pub cell: u32,
+    pub tint: [f32; 3],
+    pub alpha: f32,
+    // a mark over the middle when patterns are on, 0 for none, see `tiles::pattern`
+    pub pattern: u32,
+}
+
+impl Instance {
+    pub fn tile(x: f32, y: f32, size: f32, cell: u32) -> Self {
+        Self {
+            position: [x, y],
+            size: [size, size],
+            cell,
+            tint: [1.0, 1.0, 1.0],
+            alpha: 1.0,
+            pattern: 0,
         }
     }
 }
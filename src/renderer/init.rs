@@ -1,37 +1,24 @@
+use super::super::skin::Skin;
 use super::{
-    data::{self, Vertex},
+    data::{Instance, Vertex, QUAD},
+    instances::InstanceBuffer,
+    post::PostChain,
+    text,
     texture::Texture,
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use wgpu::util::DeviceExt;
 use winit::window::Window;
 
 impl super::Renderer {
-    pub async fn init(window: &Window, num_vertices: usize) -> Result<Self> {
+    pub async fn init(window: &Window, view: [f32; 2], skin: &Skin) -> Result<Self> {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::Backends::all());
         let surface = unsafe { instance.create_surface(window) };
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: Some("Device"),
-                    limits: wgpu::Limits::default(),
-                    features: wgpu::Features::default(),
-                },
-                None,
-            )
-            .await?;
+        let adapter = request_adapter(&instance, Some(&surface)).await?;
+        let (device, queue) = request_device(&adapter).await?;
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -42,22 +29,79 @@ impl super::Renderer {
         };
         surface.configure(&device, &config);
 
+        Self::build(adapter, device, queue, Some(surface), config, view, skin)
+    }
+
+    // without a window, for rendering straight to images
+    pub async fn headless(
+        size: winit::dpi::PhysicalSize<u32>,
+        view: [f32; 2],
+        skin: &Skin,
+    ) -> Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = request_adapter(&instance, None).await?;
+        let (device, queue) = request_device(&adapter).await?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+
+        Self::build(adapter, device, queue, None, config, view, skin)
+    }
+
+    fn build(
+        adapter: wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        surface: Option<wgpu::Surface>,
+        config: wgpu::SurfaceConfiguration,
+        view: [f32; 2],
+        skin: &Skin,
+    ) -> Result<Self> {
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+
         // texture stuff
-        let texture_bytes = include_bytes!("../images/tetris-atlas.png");
-        let texture = Texture::from_bytes(&device, &queue, texture_bytes, "Tetris Atlas Texture")?;
+        let texture =
+            Texture::from_image(&device, &queue, &skin.atlas, Some("Tile Atlas Texture"))?;
+        let font_texture =
+            Texture::from_image(&device, &queue, &text::font_atlas(), Some("Font Texture"))?;
 
         // buffer stuff
-        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Vertex Buffer"),
-            size: (std::mem::size_of::<Vertex>() * (num_vertices + 24)) as u64,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
+        let quad_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Quad Buffer"),
+            usage: wgpu::BufferUsages::VERTEX,
+            contents: bytemuck::cast_slice(&QUAD),
         });
+        // a single 10x40 board, it grows from there as needed
+        let tiles = InstanceBuffer::new(&device, "Tile Buffer", 400);
+        let overlay = InstanceBuffer::new(&device, "Overlay Buffer", 256);
+        let backdrop = InstanceBuffer::new(&device, "Backdrop Buffer", 1);
 
-        let size_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Size Buffer"),
+        let scale_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Scale Buffer"),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            contents: bytemuck::cast_slice(&[size.width as f32, size.height as f32]),
+            contents: bytemuck::cast_slice(&super::fit_scale(size, view)),
+        });
+
+        let atlas_cells = [skin.columns as f32, skin.rows as f32];
+        let atlas_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Atlas Buffer"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            contents: bytemuck::cast_slice(&atlas_cells),
+        });
+        let backdrop_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Backdrop Cells Buffer"),
+            usage: wgpu::BufferUsages::UNIFORM,
+            contents: bytemuck::cast_slice(&[1.0f32, 1.0]),
+        });
+        let font_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Font Buffer"),
+            usage: wgpu::BufferUsages::UNIFORM,
+            contents: bytemuck::cast_slice(&text::font_cells()),
         });
 
         //bindings
@@ -90,33 +134,46 @@ impl super::Renderer {
                     },
                     count: None,
                 },
-            ],
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Bind Group"),
-            layout: &bind_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &size_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
             ],
         });
 
-        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        let bind_group = create_bind_group(
+            &device,
+            &bind_layout,
+            &scale_buffer,
+            "Bind Group",
+            &texture,
+            &atlas_buffer,
+        );
+        let overlay_bind_group = create_bind_group(
+            &device,
+            &bind_layout,
+            &scale_buffer,
+            "Overlay Bind Group",
+            &font_texture,
+            &font_buffer,
+        );
+
+        let backdrop_bind_group = create_backdrop_bind_group(
+            &device,
+            &queue,
+            &bind_layout,
+            &scale_buffer,
+            &backdrop_buffer,
+            skin,
+        )?;
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Pipeline Layout"),
             bind_group_layouts: &[&bind_layout],
             push_constant_ranges: &[],
@@ -126,44 +183,26 @@ impl super::Renderer {
             device.create_shader_module(wgpu::include_wgsl!("../shaders/basic.vert.wgsl"));
         let frag_shader =
             device.create_shader_module(wgpu::include_wgsl!("../shaders/image.frag.wgsl"));
+        let pipeline = create_pipeline(
+            &device,
+            &pipeline_layout,
+            config.format,
+            &vert_shader,
+            &frag_shader,
+        );
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Pipeline"),
-            layout: Some(&layout),
-            vertex: wgpu::VertexState {
-                module: &vert_shader,
-                entry_point: "vs_main",
-                buffers: &[data::Vertex::get_buffer_layout()],
-            },
-
-            fragment: Some(wgpu::FragmentState {
-                module: &frag_shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+        // a skin's broken effect shouldn't keep the game from starting, it's drawn without
+        let post = if skin.post.is_empty() {
+            None
+        } else {
+            match PostChain::new(&device, config.format, size, &skin.post, None) {
+                Ok(post) => Some(post),
+                Err(e) => {
+                    log::error!("Post effects: {:#}", e);
+                    None
+                }
+            }
+        };
 
         Ok(Self {
             adapter,
@@ -171,11 +210,209 @@ impl super::Renderer {
             device,
             queue,
             size,
+            config,
+            view,
+            scale_buffer,
+            atlas_buffer,
+            bind_layout,
+            pipeline_layout,
             pipeline,
 
-            vertex_buffer,
+            quad_buffer,
+            tiles,
+            overlay,
 
             bind_group,
+            overlay_bind_group,
+            backdrop_bind_group,
+            backdrop_buffer,
+            backdrop,
+
+            hot_reload: None,
+            post,
+
+            cells: skin.cells.clone(),
+            patterns: skin.patterns,
+            remapped: Vec::new(),
+            background: clear_color(skin.background),
         })
     }
 }
+
+// the first adapter that will do, down to a software one when there's no GPU
+async fn request_adapter(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface>,
+) -> Result<wgpu::Adapter> {
+    for force_fallback_adapter in [false, true] {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                force_fallback_adapter,
+                compatible_surface: surface,
+            })
+            .await;
+        if let Some(adapter) = adapter {
+            return Ok(adapter);
+        }
+    }
+    bail!("No graphics adapter found")
+}
+
+async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue)> {
+    Ok(adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("Device"),
+                limits: wgpu::Limits::default(),
+                features: wgpu::Features::default(),
+            },
+            None,
+        )
+        .await?)
+}
+
+pub(super) fn clear_color([r, g, b]: [f32; 3]) -> wgpu::Color {
+    wgpu::Color {
+        r: r as f64,
+        g: g as f64,
+        b: b as f64,
+        a: 1.0,
+    }
+}
+
+pub(super) fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    scale_buffer: &wgpu::Buffer,
+    label: &str,
+    texture: &Texture,
+    cells: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: scale_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: cells,
+                    offset: 0,
+                    size: None,
+                }),
+            },
+        ],
+    })
+}
+
+// none when the skin's background is a plain color
+pub(super) fn create_backdrop_bind_group(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    scale_buffer: &wgpu::Buffer,
+    cells: &wgpu::Buffer,
+    skin: &Skin,
+) -> Result<Option<wgpu::BindGroup>> {
+    let Some(image) = &skin.background_image else {
+        return Ok(None);
+    };
+    let texture = Texture::from_image(device, queue, image, Some("Backdrop Texture"))?;
+    Ok(Some(create_bind_group(
+        device,
+        layout,
+        scale_buffer,
+        "Backdrop Bind Group",
+        &texture,
+        cells,
+    )))
+}
+
+pub(super) fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    vert_shader: &wgpu::ShaderModule,
+    frag_shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: vert_shader,
+            entry_point: "vs_main",
+            buffers: &[vertex_layout(), instance_layout()],
+        },
+
+        fragment: Some(wgpu::FragmentState {
+            module: frag_shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+const VERTEX_ATTRIBS: [wgpu::VertexAttribute; 2] =
+    wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+const INSTANCE_ATTRIBS: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+    2 => Float32x2,
+    3 => Float32x2,
+    4 => Uint32,
+    5 => Float32x3,
+    6 => Float32,
+    7 => Uint32,
+];
+
+fn vertex_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &VERTEX_ATTRIBS,
+    }
+}
+
+fn instance_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &INSTANCE_ATTRIBS,
+    }
+}
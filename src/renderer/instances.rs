@@ -0,0 +1,54 @@
+use super::data::Instance;
+
+// grows to fit whatever is drawn and only uploads when the contents change
+pub struct InstanceBuffer {
+    label: &'static str,
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    contents: Vec<Instance>,
+}
+
+impl InstanceBuffer {
+    pub fn new(device: &wgpu::Device, label: &'static str, capacity: usize) -> Self {
+        Self {
+            label,
+            buffer: create_buffer(device, label, capacity),
+            capacity,
+            contents: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[Instance]) {
+        if instances == self.contents.as_slice() {
+            return;
+        }
+
+        if instances.len() > self.capacity {
+            self.capacity = instances.len().next_power_of_two();
+            self.buffer = create_buffer(device, self.label, self.capacity);
+        }
+        if !instances.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(instances));
+        }
+
+        self.contents.clear();
+        self.contents.extend_from_slice(instances);
+    }
+
+    pub fn len(&self) -> u32 {
+        self.contents.len() as u32
+    }
+
+    pub fn slice(&self) -> wgpu::BufferSlice<'_> {
+        self.buffer.slice(..)
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, label: &str, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: (std::mem::size_of::<Instance>() * capacity.max(1)) as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,105 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::data::Instance;
+use super::text::SOLID_CELL;
+
+// pull in blocks per frame per frame
+const GRAVITY: f32 = -0.012;
+
+// every particle moves on a fixed arc from where it was emitted, so drawing one only
+// needs its age and nothing is simulated frame to frame
+#[derive(Clone, Copy)]
+struct Particle {
+    origin: [f32; 2],
+    velocity: [f32; 2],
+    size: f32,
+    tint: [f32; 3],
+    age: u32,
+    life: u32,
+}
+
+pub struct Burst {
+    // where the particles start, in blocks from the board's bottom left corner
+    pub origin: [f32; 2],
+    // how far apart they start, across and up
+    pub spread: [f32; 2],
+    pub count: usize,
+    pub speed: f32,
+    pub tints: Vec<[f32; 3]>,
+    // the same seed always throws the same particles
+    pub seed: u64,
+}
+
+pub struct Particles {
+    particles: Vec<Particle>,
+    // scales the number of particles in every burst, 0 turns them off
+    intensity: f32,
+}
+
+impl Particles {
+    pub fn new(intensity: f32) -> Self {
+        Self {
+            particles: Vec::new(),
+            intensity,
+        }
+    }
+
+    pub fn emit(&mut self, burst: &Burst) {
+        let count = (burst.count as f32 * self.intensity).round() as usize;
+        if count == 0 || burst.tints.is_empty() {
+            return;
+        }
+
+        let mut rng = StdRng::seed_from_u64(burst.seed);
+        for _ in 0..count {
+            let angle = rng.gen_range(0.0, std::f32::consts::TAU);
+            let speed = burst.speed * rng.gen_range(0.3, 1.0);
+            self.particles.push(Particle {
+                origin: [
+                    burst.origin[0] + rng.gen_range(-0.5, 0.5) * burst.spread[0],
+                    burst.origin[1] + rng.gen_range(-0.5, 0.5) * burst.spread[1],
+                ],
+                // thrown upwards more often than not
+                velocity: [angle.cos() * speed, angle.sin().abs().max(0.2) * speed],
+                size: rng.gen_range(0.1, 0.25),
+                tint: burst.tints[rng.gen_range(0, burst.tints.len())],
+                age: 0,
+                life: rng.gen_range(20, 45),
+            });
+        }
+    }
+
+    pub fn tick(&mut self) {
+        for particle in self.particles.iter_mut() {
+            particle.age += 1;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.life);
+    }
+
+    // `origin` and `block_size` place the board the particles belong to
+    pub fn instances(&self, origin: (f32, f32), block_size: f32) -> Vec<Instance> {
+        self.particles
+            .iter()
+            .map(|particle| {
+                let t = particle.age as f32;
+                let x = particle.origin[0] + particle.velocity[0] * t;
+                let y = particle.origin[1] + particle.velocity[1] * t + 0.5 * GRAVITY * t * t;
+                let fade = 1.0 - t / particle.life as f32;
+                let size = particle.size * block_size;
+
+                Instance {
+                    position: [
+                        origin.0 + x * block_size - size / 2.0,
+                        origin.1 + y * block_size - size / 2.0,
+                    ],
+                    size: [size, size],
+                    cell: SOLID_CELL,
+                    tint: particle.tint,
+                    alpha: fade,
+                    pattern: 0,
+                }
+            })
+            .collect()
+    }
+}
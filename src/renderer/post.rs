@@ -0,0 +1,329 @@
+use std::{borrow::Cow, fs, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use wgpu::util::DeviceExt;
+
+// put in front of each effect, with the bindings and the vertex shader they all share
+const COMMON: &str = include_str!("../shaders/post/common.wgsl");
+// built-in effects by name, anything ending in .wgsl is read from that file instead
+const EFFECTS: [(&str, &str); 4] = [
+    ("bloom", include_str!("../shaders/post/bloom.wgsl")),
+    ("crt", include_str!("../shaders/post/crt.wgsl")),
+    ("scanlines", include_str!("../shaders/post/scanlines.wgsl")),
+    ("vignette", include_str!("../shaders/post/vignette.wgsl")),
+];
+
+// the frame drawn into and the bind group for reading it back in the next effect
+struct Target {
+    // only read through the view, kept alive alongside it
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+// the frame drawn offscreen, then through each effect in turn on its way to the screen
+pub struct PostChain {
+    effects: Vec<String>,
+    layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipelines: Vec<wgpu::RenderPipeline>,
+    format: wgpu::TextureFormat,
+    sampler: wgpu::Sampler,
+    resolution: wgpu::Buffer,
+    // the scene goes into the first, the effects go back and forth between them
+    targets: [Target; 2],
+}
+
+impl PostChain {
+    // `shader_dir` reads the built-in effects from the source tree, for hot reloading
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        size: winit::dpi::PhysicalSize<u32>,
+        effects: &[String],
+        shader_dir: Option<&Path>,
+    ) -> Result<Self> {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        // smooth, the CRT bends the frame between pixels
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let resolution = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post Resolution Buffer"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            contents: bytemuck::cast_slice(&[size.width as f32, size.height as f32]),
+        });
+
+        let pipelines = create_pipelines(device, &pipeline_layout, format, effects, shader_dir)?;
+        let targets =
+            [0, 1].map(|_| create_target(device, &layout, &sampler, &resolution, format, size));
+        Ok(Self {
+            effects: effects.to_vec(),
+            layout,
+            pipeline_layout,
+            pipelines,
+            format,
+            sampler,
+            resolution,
+            targets,
+        })
+    }
+
+    pub fn effects(&self) -> &[String] {
+        &self.effects
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        queue.write_buffer(
+            &self.resolution,
+            0,
+            bytemuck::cast_slice(&[size.width as f32, size.height as f32]),
+        );
+        self.targets = [0, 1].map(|_| {
+            create_target(
+                device,
+                &self.layout,
+                &self.sampler,
+                &self.resolution,
+                self.format,
+                size,
+            )
+        });
+    }
+
+    // rebuilds every effect, keeping the old ones if any of them fails
+    pub fn reload(&mut self, device: &wgpu::Device, shader_dir: Option<&Path>) -> Result<()> {
+        self.pipelines = create_pipelines(
+            device,
+            &self.pipeline_layout,
+            self.format,
+            &self.effects,
+            shader_dir,
+        )?;
+        Ok(())
+    }
+
+    // where the scene is drawn before the effects
+    pub fn scene(&self) -> &wgpu::TextureView {
+        &self.targets[0].view
+    }
+
+    pub fn apply(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        for (i, pipeline) in self.pipelines.iter().enumerate() {
+            let input = &self.targets[i % 2];
+            let target = if i + 1 == self.pipelines.len() {
+                output
+            } else {
+                &self.targets[(i + 1) % 2].view
+            };
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Post Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &input.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+// built in, or a file that's there at least
+pub fn check_effect(name: &str) -> Result<()> {
+    if name.ends_with(".wgsl") {
+        if !Path::new(name).is_file() {
+            bail!("No post effect file {}", name);
+        }
+    } else if !EFFECTS.iter().any(|(effect, _)| *effect == name) {
+        let names: Vec<&str> = EFFECTS.iter().map(|(effect, _)| *effect).collect();
+        bail!(
+            "Unknown post effect {}, try {} or a .wgsl file",
+            name,
+            names.join(", ")
+        );
+    }
+    Ok(())
+}
+
+// the effect's source with the shared part in front
+fn effect_source(name: &str, shader_dir: Option<&Path>) -> Result<String> {
+    let source = if name.ends_with(".wgsl") {
+        fs::read_to_string(name).with_context(|| format!("Couldn't read {}", name))?
+    } else {
+        let (_, builtin) = EFFECTS
+            .iter()
+            .find(|(effect, _)| *effect == name)
+            .ok_or_else(|| anyhow!("Unknown post effect: {}", name))?;
+        match shader_dir {
+            Some(dir) => fs::read_to_string(dir.join("post").join(format!("{}.wgsl", name)))?,
+            None => builtin.to_string(),
+        }
+    };
+    let common = match shader_dir {
+        Some(dir) => fs::read_to_string(dir.join("post").join("common.wgsl"))?,
+        None => COMMON.to_string(),
+    };
+    Ok(format!("{}\n{}", common, source))
+}
+
+// errors are caught here rather than going to wgpu's handler, which panics
+fn create_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    effects: &[String],
+    shader_dir: Option<&Path>,
+) -> Result<Vec<wgpu::RenderPipeline>> {
+    let sources = effects
+        .iter()
+        .map(|effect| effect_source(effect, shader_dir))
+        .collect::<Result<Vec<_>>>()?;
+
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let pipelines = effects
+        .iter()
+        .zip(sources)
+        .map(|(effect, source)| {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(effect),
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(source)),
+            });
+            create_pipeline(device, layout, format, &shader)
+        })
+        .collect();
+    if let Some(error) = super::reload::block_on(device.pop_error_scope()) {
+        return Err(anyhow!("Post effects: {}", error));
+    }
+    Ok(pipelines)
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Post Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+fn create_target(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    resolution: &wgpu::Buffer,
+    format: wgpu::TextureFormat,
+    size: winit::dpi::PhysicalSize<u32>,
+) -> Target {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Post Texture"),
+        size: wgpu::Extent3d {
+            width: size.width.max(1),
+            height: size.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Post Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: resolution.as_entire_binding(),
+            },
+        ],
+    });
+    Target {
+        _texture: texture,
+        view,
+        bind_group,
+    }
+}
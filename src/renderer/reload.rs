@@ -0,0 +1,283 @@
+use std::{
+    borrow::Cow,
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+
+use super::super::skin::{Overrides, Skin};
+use super::{data::Instance, init, post::PostChain, text, texture::Texture};
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+// straight from the source tree, this is only meant for working on the game
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+const BUILTIN_ATLAS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/images/tetris-atlas.png");
+
+const ERROR_TEXT_SIZE: f32 = 0.03;
+// how long a successful reload is shown for
+const NOTICE_TIME: Duration = Duration::from_secs(2);
+const NOTICE_COLOR: [f32; 3] = [0.3, 0.9, 0.4];
+const MAX_ERROR_LINES: usize = 16;
+const MAX_ERROR_WIDTH: usize = 90;
+
+// files or whole directories, and when each file in them was last modified
+struct Watched {
+    paths: Vec<PathBuf>,
+    modified: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl Watched {
+    fn new(paths: Vec<PathBuf>) -> Self {
+        let modified = modified_times(&paths);
+        Self { paths, modified }
+    }
+
+    fn changed(&mut self) -> bool {
+        let modified = modified_times(&self.paths);
+        let changed = modified != self.modified;
+        self.modified = modified;
+        changed
+    }
+}
+
+fn modified_times(paths: &[PathBuf]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut files = Vec::new();
+    for path in paths {
+        match fs::read_dir(path) {
+            Ok(entries) => files.extend(entries.flatten().map(|entry| entry.path())),
+            Err(_) => files.push(path.clone()),
+        }
+    }
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|file| {
+            let modified = fs::metadata(&file).and_then(|meta| meta.modified()).ok();
+            (file, modified)
+        })
+        .collect()
+}
+
+pub struct HotReload {
+    skin_dir: Option<PathBuf>,
+    overrides: Overrides,
+    shaders: Watched,
+    assets: Watched,
+    last_check: Instant,
+    // kept on screen until the same kind of reload works again
+    shader_error: Option<String>,
+    asset_error: Option<String>,
+    // the last reload that worked and when
+    notice: Option<(&'static str, Instant)>,
+}
+
+impl super::Renderer {
+    // dev mode: rebuilds the pipeline and the tile atlas whenever their files are saved
+    pub fn enable_hot_reload(&mut self, skin_dir: Option<&str>, overrides: Overrides) {
+        let skin_dir = skin_dir.map(PathBuf::from);
+        let assets = match &skin_dir {
+            Some(dir) => dir.clone(),
+            None => PathBuf::from(BUILTIN_ATLAS),
+        };
+
+        log::info!(
+            "Watching {} and {} for changes",
+            SHADER_DIR,
+            assets.display()
+        );
+        self.hot_reload = Some(HotReload {
+            skin_dir,
+            overrides,
+            shaders: Watched::new(vec![
+                PathBuf::from(SHADER_DIR),
+                Path::new(SHADER_DIR).join("post"),
+            ]),
+            assets: Watched::new(vec![assets]),
+            last_check: Instant::now(),
+            shader_error: None,
+            asset_error: None,
+            notice: None,
+        });
+    }
+
+    pub(super) fn hot_reload(&mut self) {
+        let Some(mut hot_reload) = self.hot_reload.take() else {
+            return;
+        };
+
+        if hot_reload.last_check.elapsed() >= CHECK_INTERVAL {
+            hot_reload.last_check = Instant::now();
+
+            if hot_reload.shaders.changed() {
+                let result = self.reload_shaders();
+                hot_reload.shader_error = result.err().map(|e| report("Shader", e));
+                if hot_reload.shader_error.is_none() {
+                    hot_reload.notice = Some(("Reloaded shaders", Instant::now()));
+                }
+            }
+            if hot_reload.assets.changed() {
+                let result =
+                    self.reload_skin(hot_reload.skin_dir.as_deref(), &hot_reload.overrides);
+                hot_reload.asset_error = result.err().map(|e| report("Skin", e));
+                if hot_reload.asset_error.is_none() {
+                    hot_reload.notice = Some(("Reloaded skin", Instant::now()));
+                }
+            }
+        }
+
+        self.hot_reload = Some(hot_reload);
+    }
+
+    fn reload_shaders(&mut self) -> Result<()> {
+        let vert_source = fs::read_to_string(Path::new(SHADER_DIR).join("basic.vert.wgsl"))?;
+        let frag_source = fs::read_to_string(Path::new(SHADER_DIR).join("image.frag.wgsl"))?;
+
+        // errors would otherwise go to wgpu's handler, which panics
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let vert_shader = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("basic.vert.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(vert_source)),
+            });
+        let frag_shader = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("image.frag.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(frag_source)),
+            });
+        let pipeline = init::create_pipeline(
+            &self.device,
+            &self.pipeline_layout,
+            self.config.format,
+            &vert_shader,
+            &frag_shader,
+        );
+        if let Some(error) = block_on(self.device.pop_error_scope()) {
+            return Err(anyhow!("{}", error));
+        }
+
+        self.pipeline = pipeline;
+        if let Some(post) = &mut self.post {
+            post.reload(&self.device, Some(Path::new(SHADER_DIR)))?;
+        }
+        Ok(())
+    }
+
+    fn reload_skin(&mut self, dir: Option<&Path>, overrides: &Overrides) -> Result<()> {
+        let mut skin = match dir {
+            Some(dir) => Skin::load(dir)?,
+            None => Skin::from_builtin_atlas(image::open(BUILTIN_ATLAS)?),
+        };
+        skin.apply(overrides)?;
+        self.set_skin(&skin)?;
+        Ok(())
+    }
+
+    pub fn set_skin(&mut self, skin: &Skin) -> Result<()> {
+        let texture = Texture::from_image(
+            &self.device,
+            &self.queue,
+            &skin.atlas,
+            Some("Tile Atlas Texture"),
+        )?;
+        self.queue.write_buffer(
+            &self.atlas_buffer,
+            0,
+            bytemuck::cast_slice(&[skin.columns as f32, skin.rows as f32]),
+        );
+        self.bind_group = init::create_bind_group(
+            &self.device,
+            &self.bind_layout,
+            &self.scale_buffer,
+            "Bind Group",
+            &texture,
+            &self.atlas_buffer,
+        );
+        self.backdrop_bind_group = init::create_backdrop_bind_group(
+            &self.device,
+            &self.queue,
+            &self.bind_layout,
+            &self.scale_buffer,
+            &self.backdrop_buffer,
+            skin,
+        )?;
+        let effects = self.post.as_ref().map_or(&[][..], |post| post.effects());
+        if skin.post != effects {
+            self.post = if skin.post.is_empty() {
+                None
+            } else {
+                Some(PostChain::new(
+                    &self.device,
+                    self.config.format,
+                    self.size,
+                    &skin.post,
+                    None,
+                )?)
+            };
+        }
+        self.cells = skin.cells.clone();
+        self.patterns = skin.patterns;
+        self.background = init::clear_color(skin.background);
+        Ok(())
+    }
+
+    // failed reloads in red at the top left of the view, or briefly the last one that worked
+    pub(super) fn reload_instances(&self) -> Vec<Instance> {
+        let Some(hot_reload) = &self.hot_reload else {
+            return Vec::new();
+        };
+
+        let lines: Vec<&str> = [&hot_reload.shader_error, &hot_reload.asset_error]
+            .into_iter()
+            .flatten()
+            .flat_map(|error| error.lines())
+            .filter(|line| !line.trim().is_empty())
+            .take(MAX_ERROR_LINES)
+            .collect();
+
+        let x = -self.view[0] / 2.0 + ERROR_TEXT_SIZE;
+        let mut y = self.view[1] / 2.0 - ERROR_TEXT_SIZE;
+        let mut instances = Vec::new();
+        for line in lines {
+            let line: String = line.chars().take(MAX_ERROR_WIDTH).collect();
+            for mut instance in text::text_instances(&line, x, y, ERROR_TEXT_SIZE) {
+                instance.tint = [0.9, 0.1, 0.1];
+                instances.push(instance);
+            }
+            y -= ERROR_TEXT_SIZE * 1.5;
+        }
+        if let Some((notice, at)) = hot_reload.notice {
+            if at.elapsed() < NOTICE_TIME {
+                for mut instance in text::text_instances(notice, x, y, ERROR_TEXT_SIZE) {
+                    instance.tint = NOTICE_COLOR;
+                    instances.push(instance);
+                }
+            }
+        }
+        instances
+    }
+}
+
+fn report(kind: &str, error: anyhow::Error) -> String {
+    let message = format!("{} reload failed: {:#}", kind, error);
+    eprintln!("{}", message);
+    message
+}
+
+// wgpu hands back error scopes already resolved on native, so this never really waits
+pub(super) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        std::thread::yield_now();
+    }
+}
@@ -1,14 +1,65 @@
-use super::data::Vertex;
+use std::num::NonZeroU32;
+
+use anyhow::{anyhow, Result};
+
+use super::data::{Instance, QUAD};
+use super::ImageRenderer;
 
 impl super::Renderer {
-    pub fn render(&self, vertex_data: &Vec<Vertex>) -> Result<(), wgpu::SurfaceError> {
-        self.queue
-            .write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(vertex_data));
+    pub fn render(
+        &mut self,
+        tiles: &[Instance],
+        overlay: &[Instance],
+    ) -> Result<(), wgpu::SurfaceError> {
+        let Some(surface) = &self.surface else {
+            return Ok(());
+        };
 
-        let output_texture = self.surface.get_current_texture()?;
+        let output_texture = surface.get_current_texture()?;
         let output_view = output_texture
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
+        self.draw(&output_view, tiles, overlay);
+
+        output_texture.present();
+        Ok(())
+    }
+
+    // `render`, getting over a lost surface and giving up on anything worse
+    pub fn show(&mut self, tiles: &[Instance], overlay: &[Instance]) -> Result<()> {
+        match self.render(tiles, overlay) {
+            Ok(()) => {}
+            Err(wgpu::SurfaceError::Lost) => self.resize(self.size),
+            Err(wgpu::SurfaceError::OutOfMemory) => anyhow::bail!("Out of graphics memory"),
+            Err(e) => eprintln!("{:?}", e),
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, target: &wgpu::TextureView, tiles: &[Instance], overlay: &[Instance]) {
+        self.hot_reload();
+
+        // tiles come in numbered like `tiles::cell`, the skin decides what each looks like
+        self.remapped.clear();
+        self.remapped.extend(tiles.iter().filter_map(|tile| {
+            let cell = self.cells.get(tile.cell as usize).copied().flatten()?;
+            let pattern = if self.patterns { tile.pattern } else { 0 };
+            Some(Instance {
+                cell,
+                pattern,
+                ..*tile
+            })
+        }));
+        self.tiles.update(&self.device, &self.queue, &self.remapped);
+        let backdrop = super::backdrop_instance(self.size, self.view);
+        self.backdrop.update(&self.device, &self.queue, &[backdrop]);
+        let reloads = self.reload_instances();
+        if reloads.is_empty() {
+            self.overlay.update(&self.device, &self.queue, overlay);
+        } else {
+            let overlay = [overlay, reloads.as_slice()].concat();
+            self.overlay.update(&self.device, &self.queue, &overlay);
+        }
 
         let mut command_encoder =
             self.device
@@ -16,18 +67,15 @@ impl super::Renderer {
                     label: Some("Command Encoder"),
                 });
 
+        // drawn offscreen first when there are effects to put it through
+        let scene = self.post.as_ref().map_or(target, |post| post.scene());
         let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &output_view,
+                view: scene,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 1.0,
-                        g: 1.0,
-                        b: 1.0,
-                        a: 1.0,
-                    }),
+                    load: wgpu::LoadOp::Clear(self.background),
                     store: true,
                 },
             })],
@@ -36,15 +84,109 @@ impl super::Renderer {
 
         render_pass.set_pipeline(&self.pipeline);
 
+        render_pass.set_vertex_buffer(0, self.quad_buffer.slice(..));
+
+        if let Some(backdrop_bind_group) = &self.backdrop_bind_group {
+            render_pass.set_bind_group(0, backdrop_bind_group, &[]);
+            render_pass.set_vertex_buffer(1, self.backdrop.slice());
+            render_pass.draw(0..QUAD.len() as u32, 0..self.backdrop.len());
+        }
+
         render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.tiles.slice());
+        render_pass.draw(0..QUAD.len() as u32, 0..self.tiles.len());
 
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.draw(0..vertex_data.len() as u32, 0..1);
+        render_pass.set_bind_group(0, &self.overlay_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.overlay.slice());
+        render_pass.draw(0..QUAD.len() as u32, 0..self.overlay.len());
 
         drop(render_pass);
+        if let Some(post) = &self.post {
+            post.apply(&mut command_encoder, target);
+        }
         self.queue.submit(std::iter::once(command_encoder.finish()));
+    }
+}
 
-        output_texture.present();
-        Ok(())
+impl ImageRenderer for super::Renderer {
+    // the same frame `render` would show, read back from an offscreen texture
+    fn render_to_image(
+        &mut self,
+        tiles: &[Instance],
+        overlay: &[Instance],
+    ) -> Result<image::RgbaImage> {
+        let (width, height) = (self.size.width, self.size.height);
+        let extent = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Texture"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.draw(&view, tiles, overlay);
+
+        // rows copied out of a texture have to start on a 256 byte boundary
+        let row_bytes = 4 * width;
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_bytes = row_bytes.div_ceil(alignment) * alignment;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_row_bytes * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut command_encoder =
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Readback Encoder"),
+                });
+        command_encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row_bytes),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+            },
+            extent,
+        );
+        self.queue.submit(std::iter::once(command_encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let mut pixels = Vec::with_capacity((row_bytes * height) as usize);
+        for row in slice.get_mapped_range().chunks(padded_row_bytes as usize) {
+            pixels.extend_from_slice(&row[..row_bytes as usize]);
+        }
+        buffer.unmap();
+
+        // window surfaces are usually BGRA
+        if let wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb =
+            self.config.format
+        {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| anyhow!("Read back the wrong number of pixels"))
     }
 }
@@ -0,0 +1,205 @@
+use anyhow::Result;
+use winit::dpi::PhysicalSize;
+
+use super::super::skin::Skin;
+use super::{data::Instance, text, ImageRenderer};
+
+// an atlas already decoded to linear colour, split into `columns` x `rows` cells
+struct Atlas {
+    width: u32,
+    height: u32,
+    columns: u32,
+    rows: u32,
+    texels: Vec<[f32; 4]>,
+}
+
+impl Atlas {
+    fn new(image: &image::DynamicImage, columns: u32, rows: u32) -> Self {
+        let image = image.to_rgba8();
+        let texels = image
+            .pixels()
+            .map(|pixel| {
+                let [r, g, b, a] = pixel.0;
+                [to_linear(r), to_linear(g), to_linear(b), a as f32 / 255.0]
+            })
+            .collect();
+
+        Self {
+            width: image.width(),
+            height: image.height(),
+            columns,
+            rows,
+            texels,
+        }
+    }
+
+    // the nearest texel, `u` and `v` going from 0 to 1 across the cell from its top left
+    fn sample(&self, cell: u32, u: f32, v: f32) -> [f32; 4] {
+        let x = ((cell % self.columns) as f32 + u) / self.columns as f32 * self.width as f32;
+        let y = ((cell / self.columns) as f32 + v) / self.rows as f32 * self.height as f32;
+        let x = (x as u32).min(self.width - 1);
+        let y = (y as u32).min(self.height - 1);
+        self.texels[(y * self.width + x) as usize]
+    }
+}
+
+// draws the same frames as the wgpu renderer, without needing a graphics adapter
+pub struct SoftwareRenderer {
+    size: PhysicalSize<u32>,
+    view: [f32; 2],
+    tiles: Atlas,
+    font: Atlas,
+    // the skin's background image, stretched over the whole image
+    backdrop: Option<Atlas>,
+    cells: Vec<Option<u32>>,
+    patterns: bool,
+    background: [f32; 3],
+}
+
+impl SoftwareRenderer {
+    pub fn new(size: PhysicalSize<u32>, view: [f32; 2], skin: &Skin) -> Self {
+        let [font_columns, font_rows] = text::font_cells();
+        // they're shaders, there's nothing to run them here
+        if !skin.post.is_empty() {
+            eprintln!("Post effects need wgpu, drawing without them");
+        }
+
+        Self {
+            size,
+            view,
+            tiles: Atlas::new(&skin.atlas, skin.columns, skin.rows),
+            font: Atlas::new(&text::font_atlas(), font_columns as u32, font_rows as u32),
+            backdrop: skin
+                .background_image
+                .as_ref()
+                .map(|image| Atlas::new(image, 1, 1)),
+            cells: skin.cells.clone(),
+            patterns: skin.patterns,
+            background: skin.background,
+        }
+    }
+
+    // fills the pixels whose centres fall inside each instance, blended like the pipeline
+    fn draw(&self, pixels: &mut [[f32; 3]], atlas: &Atlas, instances: &[Instance]) {
+        let (width, height) = (self.size.width as f32, self.size.height as f32);
+        let pixels_per_unit = super::pixels_per_unit(self.size, self.view);
+
+        for instance in instances {
+            let left = width / 2.0 + instance.position[0] * pixels_per_unit;
+            let right = left + instance.size[0] * pixels_per_unit;
+            let bottom = height / 2.0 - instance.position[1] * pixels_per_unit;
+            let top = bottom - instance.size[1] * pixels_per_unit;
+
+            let columns = pixel_range(left, right, self.size.width);
+            let rows = pixel_range(top, bottom, self.size.height);
+            for y in rows {
+                let v = (y as f32 + 0.5 - top) / (bottom - top);
+                for x in columns.clone() {
+                    let u = (x as f32 + 0.5 - left) / (right - left);
+                    let [r, g, b, a] = atlas.sample(instance.cell, u, v);
+                    // the font atlas is transparent around the glyphs
+                    if a < 0.5 {
+                        continue;
+                    }
+
+                    let shade = if pattern_covers(instance.pattern, u, v) {
+                        PATTERN_SHADE
+                    } else {
+                        1.0
+                    };
+                    let [tint_r, tint_g, tint_b] = instance.tint;
+                    let source = [r * tint_r, g * tint_g, b * tint_b].map(|c| c * shade);
+                    let alpha = a * instance.alpha;
+                    let pixel = &mut pixels[(y * self.size.width + x) as usize];
+                    for (channel, source) in pixel.iter_mut().zip(source) {
+                        *channel = source * alpha + *channel * (1.0 - alpha);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl ImageRenderer for SoftwareRenderer {
+    fn render_to_image(
+        &mut self,
+        tiles: &[Instance],
+        overlay: &[Instance],
+    ) -> Result<image::RgbaImage> {
+        // tiles come in numbered like `tiles::cell`, the skin decides what each looks like
+        let tiles: Vec<Instance> = tiles
+            .iter()
+            .filter_map(|tile| {
+                let cell = self.cells.get(tile.cell as usize).copied().flatten()?;
+                let pattern = if self.patterns { tile.pattern } else { 0 };
+                Some(Instance {
+                    cell,
+                    pattern,
+                    ..*tile
+                })
+            })
+            .collect();
+
+        let (width, height) = (self.size.width, self.size.height);
+        let mut pixels = vec![self.background; (width * height) as usize];
+        if let Some(backdrop) = &self.backdrop {
+            let instance = super::backdrop_instance(self.size, self.view);
+            self.draw(&mut pixels, backdrop, &[instance]);
+        }
+        self.draw(&mut pixels, &self.tiles, &tiles);
+        self.draw(&mut pixels, &self.font, overlay);
+
+        Ok(image::RgbaImage::from_fn(width, height, |x, y| {
+            let [r, g, b] = pixels[(y * width + x) as usize];
+            image::Rgba([to_srgb(r), to_srgb(g), to_srgb(b), 255])
+        }))
+    }
+}
+
+// the same marks as `pattern_covers` in image.frag.wgsl
+const PATTERN_SHADE: f32 = 0.45;
+
+fn pattern_covers(pattern: u32, u: f32, v: f32) -> bool {
+    let (dx, dy) = ((u - 0.5).abs(), (v - 0.5).abs());
+    let inside = dx.max(dy) < 0.3;
+    let falling = (u - v).abs() < 0.08;
+    let rising = (u + v - 1.0).abs() < 0.08;
+    match pattern {
+        1 => dx.hypot(dy) < 0.18,
+        2 => inside && falling,
+        3 => inside && rising,
+        4 => inside && (dy - 0.15).abs() < 0.06,
+        5 => dx.max(dy) < 0.3 && dx.min(dy) < 0.07,
+        6 => dx.max(dy) < 0.25 && dx.max(dy) > 0.13,
+        7 => inside && (dx - 0.15).abs() < 0.06,
+        8 => inside && (falling || rising),
+        _ => false,
+    }
+}
+
+// pixels from `start` to `end` whose centres are covered, kept inside the image
+fn pixel_range(start: f32, end: f32, limit: u32) -> std::ops::Range<u32> {
+    let first = (start - 0.5).ceil().clamp(0.0, limit as f32) as u32;
+    let last = (end - 0.5).ceil().clamp(0.0, limit as f32) as u32;
+    first..last
+}
+
+// the wgpu target is sRGB, so blending there happens on linear colour too
+fn to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value * 255.0).round() as u8
+}
@@ -0,0 +1,148 @@
+use super::data::Instance;
+
+// glyphs are 5x7 pixels, each row a byte with the leftmost pixel in bit 4
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+// one pixel of padding around each glyph in the atlas keeps sampling clean
+const CELL_WIDTH: u32 = GLYPH_WIDTH + 1;
+const CELL_HEIGHT: u32 = GLYPH_HEIGHT + 1;
+
+// the font only has capitals, text is upper-cased before drawing
+const GLYPHS: [(char, [u8; 7]); 60] = [
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    ('A', [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    ('>', [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08]),
+    ('<', [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02]),
+    ('|', [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('[', [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E]),
+    (']', [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E]),
+    ('\'', [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    ('*', [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00]),
+    ('#', [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A]),
+    ('$', [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04]),
+    ('&', [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D]),
+];
+
+// light, to stand out against the dark backgrounds skins tend to have
+pub const TEXT_COLOR: [f32; 3] = [0.9, 0.9, 0.9];
+
+// a fully opaque cell after the glyphs, tinted for flashes and other effects
+pub const SOLID_CELL: u32 = GLYPHS.len() as u32;
+
+// the glyphs side by side in one row, opaque white on transparent so they can be tinted
+pub fn font_atlas() -> image::DynamicImage {
+    let mut atlas = image::RgbaImage::new(CELL_WIDTH * (SOLID_CELL + 1), CELL_HEIGHT);
+
+    for (index, (_, rows)) in GLYPHS.iter().enumerate() {
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if row & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                    let pixel_x = index as u32 * CELL_WIDTH + x;
+                    atlas.put_pixel(pixel_x, y as u32, image::Rgba([255, 255, 255, 255]));
+                }
+            }
+        }
+    }
+    for x in 0..CELL_WIDTH {
+        for y in 0..CELL_HEIGHT {
+            atlas.put_pixel(
+                SOLID_CELL * CELL_WIDTH + x,
+                y,
+                image::Rgba([255, 255, 255, 255]),
+            );
+        }
+    }
+
+    image::DynamicImage::ImageRgba8(atlas)
+}
+
+pub fn font_cells() -> [f32; 2] {
+    [(SOLID_CELL + 1) as f32, 1.0]
+}
+
+// width of a line of text drawn `size` tall
+pub fn text_width(text: &str, size: f32) -> f32 {
+    text.chars().count() as f32 * size * CELL_WIDTH as f32 / GLYPH_HEIGHT as f32
+}
+
+// a line centred on `x`, with its top at `y`
+pub fn centered_text_instances(text: &str, x: f32, y: f32, size: f32) -> Vec<Instance> {
+    text_instances(text, x - text_width(text, size) / 2.0, y, size)
+}
+
+// one instance per glyph with the line's top left corner at `x`, `y`
+pub fn text_instances(text: &str, x: f32, y: f32, size: f32) -> Vec<Instance> {
+    let pixel = size / GLYPH_HEIGHT as f32;
+    let cell_size = [CELL_WIDTH as f32 * pixel, CELL_HEIGHT as f32 * pixel];
+
+    text.chars()
+        .enumerate()
+        .filter_map(|(i, c)| {
+            let c = c.to_ascii_uppercase();
+            let index = GLYPHS
+                .iter()
+                .position(|(glyph, _)| *glyph == c)
+                .unwrap_or(1);
+            if index == 0 {
+                return None;
+            }
+
+            Some(Instance {
+                position: [x + i as f32 * cell_size[0], y - cell_size[1]],
+                size: cell_size,
+                cell: index as u32,
+                tint: TEXT_COLOR,
+                alpha: 1.0,
+                pattern: 0,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,218 @@
+use std::cell::RefCell;
+
+use super::super::board::{Block, Board};
+use super::super::tetrimino::{Color, Tetrimino};
+use super::data::Instance;
+
+// past the colors and the empty cell, left undrawn unless the skin has them
+pub const FRAME_CELL: u32 = 9;
+pub const GRID_CELL: u32 = 10;
+// how thick the frame is, in blocks
+pub const FRAME_WIDTH: f32 = 0.25;
+const GRID_WIDTH: f32 = 0.06;
+const GRID_ALPHA: f32 = 0.25;
+// how faded the hinted rows of the buffer zone are
+const HINT_ALPHA: f32 = 0.35;
+
+// which cell of the tile atlas a block of this color is drawn with
+pub fn cell(color: Color) -> u32 {
+    match color {
+        Color::Blue => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Orange => 3,
+        Color::Purple => 4,
+        Color::Yellow => 5,
+        Color::LightBlue => 6,
+        Color::Garbage => 7,
+        _ => 8,
+    }
+}
+
+// the mark each piece's tiles and garbage get when patterns are on, from 1 up
+pub fn pattern(cell: u32) -> u32 {
+    let garbage = self::cell(Color::Garbage);
+    if cell <= garbage {
+        cell + 1
+    } else {
+        0
+    }
+}
+
+// a piece in its spawn orientation hanging down from `top`, for the next queue and hold
+pub fn piece_instances(tetrimino: &Tetrimino, left: f32, top: f32, block: f32) -> Vec<Instance> {
+    let cell = cell(tetrimino.get_color());
+    tetrimino
+        .preview()
+        .into_iter()
+        .map(|(x, y)| Instance {
+            pattern: pattern(cell),
+            ..Instance::tile(
+                left + x as f32 * block,
+                top - (y + 1) as f32 * block,
+                block,
+                cell,
+            )
+        })
+        .collect()
+}
+
+// each board's tiles as of its last change, most frames nothing moves
+#[derive(Default)]
+pub struct TileCache {
+    boards: RefCell<Vec<Option<Built>>>,
+}
+
+#[derive(Clone)]
+struct Built {
+    revision: u64,
+    tiles: Vec<Instance>,
+}
+
+impl TileCache {
+    pub fn board_instances(&self, slot: usize, board: &Board) -> Vec<Instance> {
+        let mut boards = self.boards.borrow_mut();
+        if boards.len() <= slot {
+            boards.resize(slot + 1, None);
+        }
+        match &boards[slot] {
+            Some(built) if built.revision == board.revision() => built.tiles.clone(),
+            _ => {
+                let tiles = board_instances(board);
+                boards[slot] = Some(Built {
+                    revision: board.revision(),
+                    tiles: tiles.clone(),
+                });
+                tiles
+            }
+        }
+    }
+}
+
+// the stack, the falling piece and the frame around them
+pub fn board_instances(board: &Board) -> Vec<Instance> {
+    let (width, visible) = (board.width(), board.visible_height());
+    let hinted = (visible + board.buffer_hint()).min(board.height());
+    let mut instances = Vec::with_capacity(width * hinted + 4);
+
+    let empty = cell(Color::Empty);
+    for y in 0..hinted {
+        for x in 0..width {
+            // rows being cleared are drawn by the animations, if at all
+            let cell = match board.get_block_at(x, y) {
+                Some(Block { ty }) if !board.clearing().contains(&y) => cell(*ty),
+                _ => empty,
+            };
+            // above the playfield only what's actually there is drawn
+            if y >= visible && cell == empty {
+                continue;
+            }
+            instances.push(block_instance(board, x as i32, y as i32, cell));
+        }
+    }
+
+    if board.grid() {
+        instances.extend(grid_instances(board));
+    }
+
+    if let Some(tetrimino) = &board.current_tetrimino {
+        let cell = cell(tetrimino.get_color());
+        for (x, y) in board.current_cells() {
+            if y < hinted as i32 {
+                instances.push(block_instance(board, x, y, cell));
+            }
+        }
+    }
+
+    instances.extend(frame_instances(board));
+    instances
+}
+
+// faded when it's up in the buffer zone
+fn block_instance(board: &Board, x: i32, y: i32, cell: u32) -> Instance {
+    let (left, bottom) = board.origin();
+    let block = board.block_size();
+    let alpha = if y >= board.visible_height() as i32 {
+        HINT_ALPHA
+    } else {
+        1.0
+    };
+    Instance {
+        alpha,
+        pattern: pattern(cell),
+        ..Instance::tile(
+            left + x as f32 * block,
+            bottom + y as f32 * block,
+            block,
+            cell,
+        )
+    }
+}
+
+// thin lines between the cells of the playfield
+fn grid_instances(board: &Board) -> Vec<Instance> {
+    let (left, bottom) = board.origin();
+    let block = board.block_size();
+    let thickness = GRID_WIDTH * block;
+    let (columns, rows) = (board.width(), board.visible_height());
+    let (width, height) = (columns as f32 * block, rows as f32 * block);
+
+    let columns = (1..columns).map(|x| Instance {
+        size: [thickness, height],
+        alpha: GRID_ALPHA,
+        ..Instance::tile(
+            left + x as f32 * block - thickness / 2.0,
+            bottom,
+            block,
+            GRID_CELL,
+        )
+    });
+    let rows = (1..rows).map(|y| Instance {
+        size: [width, thickness],
+        alpha: GRID_ALPHA,
+        ..Instance::tile(
+            left,
+            bottom + y as f32 * block - thickness / 2.0,
+            block,
+            GRID_CELL,
+        )
+    });
+    columns.chain(rows).collect()
+}
+
+// down both sides and along the bottom, one piece per block so the cell isn't stretched
+fn frame_instances(board: &Board) -> Vec<Instance> {
+    let (left, bottom) = board.origin();
+    let block = board.block_size();
+    let thickness = FRAME_WIDTH * block;
+    let right = left + board.width() as f32 * block;
+
+    let mut instances = Vec::new();
+    for y in 0..board.visible_height() {
+        let y = bottom + y as f32 * block;
+        for x in [left - thickness, right] {
+            instances.push(Instance {
+                size: [thickness, block],
+                ..Instance::tile(x, y, block, FRAME_CELL)
+            });
+        }
+    }
+    for x in 0..board.width() {
+        instances.push(Instance {
+            size: [block, thickness],
+            ..Instance::tile(
+                left + x as f32 * block,
+                bottom - thickness,
+                block,
+                FRAME_CELL,
+            )
+        });
+    }
+    for x in [left - thickness, right] {
+        instances.push(Instance {
+            size: [thickness, thickness],
+            ..Instance::tile(x, bottom - thickness, block, FRAME_CELL)
+        });
+    }
+    instances
+}
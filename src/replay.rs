@@ -0,0 +1,288 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, LineWriter, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use winit::dpi::PhysicalSize;
+
+use super::game;
+use super::input::Input;
+use super::options::Options;
+use super::renderer::{ImageRenderer, SoftwareRenderer};
+
+// a replay is how a local game was started and what the player pressed, one line each:
+//
+//   arg --mode            every argument the game was started with, in order
+//   arg marathon
+//   seed 1234
+//   12 16                 the frame and the input bits, for frames with anything pressed
+//   end 3600              how many frames were played, missing if the game never finished
+const ARG: &str = "arg";
+const SEED: &str = "seed";
+const END: &str = "end";
+
+// arguments that only matter to the run that's recording
+const SKIPPED_ARGS: [&str; 2] = ["--record", "--seed"];
+
+// writes a replay as the game goes, so a crash still leaves everything up to it
+pub struct Recorder {
+    // none once it's finished or failed
+    file: Option<LineWriter<File>>,
+    frame: u32,
+}
+
+impl Recorder {
+    pub fn create(path: &str, args: &[String], seed: u64) -> Result<Self> {
+        let mut file = LineWriter::new(
+            File::create(path).with_context(|| format!("Couldn't create {}", path))?,
+        );
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if SKIPPED_ARGS.contains(&arg.as_str()) {
+                args.next();
+                continue;
+            }
+            writeln!(file, "{} {}", ARG, arg)?;
+        }
+        writeln!(file, "{} {}", SEED, seed)?;
+
+        Ok(Self {
+            file: Some(file),
+            frame: 0,
+        })
+    }
+
+    // once a frame, with whatever was pressed during it
+    pub fn record(&mut self, input: Input) {
+        if !input.is_empty() {
+            let line = format!("{} {}", self.frame, input.0);
+            self.write(&line);
+        }
+        self.frame += 1;
+    }
+
+    pub fn finish(&mut self) {
+        let line = format!("{} {}", END, self.frame);
+        self.write(&line);
+        self.file = None;
+    }
+
+    fn write(&mut self, line: &str) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        if let Err(e) = writeln!(file, "{}", line) {
+            eprintln!("Stopped recording: {}", e);
+            self.file = None;
+        }
+    }
+}
+
+pub struct Replay {
+    pub args: Vec<String>,
+    pub seed: u64,
+    inputs: HashMap<u32, Input>,
+    // frames in the whole game
+    pub length: u32,
+}
+
+impl Replay {
+    pub fn load(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path))?;
+
+        let mut args = Vec::new();
+        let mut seed = None;
+        let mut inputs = HashMap::new();
+        let mut end = None;
+        for (number, line) in text.lines().enumerate() {
+            let (key, value) = line
+                .split_once(' ')
+                .ok_or_else(|| anyhow!("Line {}: expected two values", number + 1))?;
+            match key {
+                ARG => args.push(value.to_string()),
+                SEED => seed = Some(value.parse()?),
+                END => end = Some(value.parse()?),
+                _ => {
+                    let frame: u32 = key
+                        .parse()
+                        .map_err(|_| anyhow!("Line {}: unknown key {}", number + 1, key))?;
+                    inputs.insert(frame, Input(value.parse()?));
+                }
+            }
+        }
+
+        let seed = seed.ok_or_else(|| anyhow!("{} has no seed", path))?;
+        // a game that was cut short ends with its last input
+        let length = end.unwrap_or_else(|| inputs.keys().max().map_or(0, |frame| frame + 1));
+        Ok(Self {
+            args,
+            seed,
+            inputs,
+            length,
+        })
+    }
+
+    pub fn input(&self, frame: u32) -> Input {
+        self.inputs.get(&frame).copied().unwrap_or(Input::NONE)
+    }
+}
+
+enum Encoder {
+    Gif(image::codecs::gif::GifEncoder<BufWriter<File>>),
+    Apng(png::Writer<BufWriter<File>>),
+}
+
+impl Encoder {
+    // picked by the output's extension
+    fn create(output: &Path, size: PhysicalSize<u32>, frames: u32, fps: u32) -> Result<Self> {
+        let file = BufWriter::new(
+            File::create(output)
+                .with_context(|| format!("Couldn't create {}", output.display()))?,
+        );
+
+        match output.extension().and_then(|extension| extension.to_str()) {
+            Some("gif") => {
+                let mut encoder = image::codecs::gif::GifEncoder::new(file);
+                encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+                Ok(Self::Gif(encoder))
+            }
+            Some("png" | "apng") => {
+                let mut encoder = png::Encoder::new(file, size.width, size.height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(frames, 0)?;
+                encoder.set_frame_delay(1, fps as u16)?;
+                Ok(Self::Apng(encoder.write_header()?))
+            }
+            _ => bail!("Clips are saved as .gif, .png or .apng"),
+        }
+    }
+
+    fn add(&mut self, frame: image::RgbaImage, fps: u32) -> Result<()> {
+        match self {
+            Self::Gif(encoder) => {
+                let delay = image::Delay::from_numer_denom_ms(1000, fps);
+                encoder.encode_frame(image::Frame::from_parts(frame, 0, 0, delay))?;
+            }
+            Self::Apng(writer) => writer.write_image_data(&frame)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            Self::Gif(encoder) => drop(encoder),
+            Self::Apng(writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
+
+// plays the replay back without a window and draws `options.frames` of it on the CPU
+pub fn export(path: &str, output: &Path, options: &Options) -> Result<()> {
+    let replay = Replay::load(path)?;
+    let mut game_options = Options::parse(replay.args.clone())?;
+    game_options.seed = Some(replay.seed);
+
+    let session = super::new_session(&game_options)?;
+    let mut session = if game_options.animations {
+        session.with_animations(game_options.particles)
+    } else {
+        session
+    };
+
+    let window = super::window_size(session.players.len());
+    let size = super::pixel_size(window, options.scale);
+    let skin = super::load_skin(options);
+    let mut renderer = SoftwareRenderer::new(size, super::view_size(window), &skin);
+
+    let start = options.frames.0.min(replay.length);
+    let end = options.frames.1.unwrap_or(replay.length).min(replay.length);
+    if start >= end {
+        bail!("No frames between {} and {}", start, end);
+    }
+    // the game runs at a fixed rate, the clip takes every few frames of it
+    let game_fps = (1.0 / game::FRAME.as_secs_f64()).round() as u32;
+    let frames = ((end - start) as u64 * options.fps as u64).div_ceil(game_fps as u64) as u32;
+
+    let mut encoder = Encoder::create(output, size, frames, options.fps)?;
+    let mut next = 0;
+    for frame in 0..end {
+        session.press(replay.input(frame));
+        session.tick();
+
+        let due = start + (next as u64 * game_fps as u64 / options.fps as u64) as u32;
+        if frame == due && next < frames {
+            let image =
+                renderer.render_to_image(&session.to_instances(), &session.overlay_instances())?;
+            encoder.add(image, options.fps)?;
+            next += 1;
+        }
+    }
+    encoder.finish()?;
+
+    println!("{}", session.status());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}.txt", name, std::process::id()));
+        path.to_string_lossy().into_owned()
+    }
+
+    fn record(path: &str, inputs: &[Input], finish: bool) {
+        let args: Vec<String> = ["--mode", "sprint", "--record", path, "--seed", "5"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let mut recorder = Recorder::create(path, &args, 1234).unwrap();
+        for &input in inputs {
+            recorder.record(input);
+        }
+        if finish {
+            recorder.finish();
+        }
+    }
+
+    #[test]
+    fn replay_round_trips() {
+        let path = temp_path("replay-round-trip");
+        let inputs = [
+            Input::NONE,
+            Input::LEFT | Input::ROTATE_RIGHT,
+            Input::NONE,
+            Input::HOLD,
+            Input::HARD_DROP,
+            Input::NONE,
+        ];
+        record(&path, &inputs, true);
+
+        let replay = Replay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        // the recording's own arguments are left out
+        assert_eq!(replay.args, ["--mode", "sprint"]);
+        assert_eq!(replay.seed, 1234);
+        assert_eq!(replay.length, inputs.len() as u32);
+        for (frame, &input) in inputs.iter().enumerate() {
+            assert_eq!(replay.input(frame as u32), input);
+        }
+    }
+
+    #[test]
+    fn unfinished_replay_ends_with_its_last_input() {
+        let path = temp_path("replay-unfinished");
+        record(&path, &[Input::NONE, Input::RIGHT, Input::NONE], false);
+
+        let replay = Replay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replay.length, 2);
+    }
+}
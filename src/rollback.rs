@@ -0,0 +1,284 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use super::battle::Battle;
+use super::game::Game;
+use super::input::Input;
+
+// how far the simulation may run ahead of the slowest peer's confirmed inputs
+pub const MAX_ROLLBACK: u32 = 30;
+// the delay counts against that, past a third of it there's little left to predict with
+pub const MAX_INPUT_DELAY: u32 = MAX_ROLLBACK / 3;
+
+pub struct Rollback {
+    pub games: Vec<Game>,
+    pub battle: Battle,
+    pub frame: u32,
+    local: usize,
+    input_delay: u32,
+
+    // inputs we know for sure, per slot
+    inputs: Vec<BTreeMap<u32, Input>>,
+    // inputs each frame was actually simulated with, to spot wrong predictions
+    used: Vec<BTreeMap<u32, Input>>,
+    // the first frame each slot hasn't confirmed yet
+    confirmed: Vec<u32>,
+    // the frame a player who left is knocked out on
+    departed: Vec<Option<u32>>,
+    // local inputs that still have to go out to the other players
+    unsent: Vec<(u32, Input)>,
+    // game state at the start of each unconfirmed frame
+    snapshots: VecDeque<(u32, Vec<Game>, Battle)>,
+}
+
+impl Rollback {
+    pub fn new(games: Vec<Game>, battle: Battle, local: usize, input_delay: u32) -> Self {
+        let slots = games.len();
+
+        let mut rollback = Self {
+            games,
+            battle,
+            frame: 0,
+            local,
+            input_delay,
+            inputs: vec![BTreeMap::new(); slots],
+            used: vec![BTreeMap::new(); slots],
+            confirmed: vec![0; slots],
+            departed: vec![None; slots],
+            unsent: Vec::new(),
+            snapshots: VecDeque::new(),
+        };
+
+        // nothing can be pressed during our own input delay at the start
+        for frame in 0..input_delay {
+            rollback.confirm(local, frame, Input::NONE);
+            rollback.unsent.push((frame, Input::NONE));
+        }
+        rollback
+    }
+
+    pub fn add_local_input(&mut self, input: Input) {
+        let frame = self.frame + self.input_delay;
+        self.confirm(self.local, frame, input);
+        self.unsent.push((frame, input));
+    }
+
+    pub fn take_unsent(&mut self) -> Vec<(u32, Input)> {
+        std::mem::take(&mut self.unsent)
+    }
+
+    // returns the frame to roll back to if this input contradicts a prediction
+    pub fn add_remote_input(&mut self, slot: usize, frame: u32, input: Input) -> Option<u32> {
+        if slot >= self.games.len() || slot == self.local || frame < self.confirmed[slot] {
+            return None;
+        }
+        self.confirm(slot, frame, input);
+
+        match self.used[slot].get(&frame) {
+            Some(&used) if used != input => Some(frame),
+            _ => None,
+        }
+    }
+
+    // every peer got the same inputs before the goodbye, so they all knock the
+    // player out on the same frame
+    pub fn remove_player(&mut self, slot: usize) -> Option<u32> {
+        if slot >= self.games.len() || slot == self.local || self.departed[slot].is_some() {
+            return None;
+        }
+        let frame = self.confirmed[slot];
+        self.departed[slot] = Some(frame);
+        self.confirmed[slot] = u32::MAX;
+
+        (frame < self.frame).then_some(frame)
+    }
+
+    fn confirm(&mut self, slot: usize, frame: u32, input: Input) {
+        self.inputs[slot].insert(frame, input);
+        while self.inputs[slot].contains_key(&self.confirmed[slot]) {
+            self.confirmed[slot] += 1;
+        }
+    }
+
+    fn oldest_confirmed(&self) -> u32 {
+        self.confirmed.iter().copied().min().unwrap_or(self.frame)
+    }
+
+    // stall instead of predicting too far into the future
+    pub fn can_advance(&self) -> bool {
+        self.frame < self.oldest_confirmed().saturating_add(MAX_ROLLBACK)
+    }
+
+    // the latest state no future input can change anymore
+    pub fn confirmed_games(&self) -> &[Game] {
+        match self.snapshots.front() {
+            Some((_, games, _)) if self.oldest_confirmed() < self.frame => games,
+            _ => &self.games,
+        }
+    }
+
+    pub fn rollback_and_resimulate(&mut self, frame: u32) {
+        let target = self.frame;
+
+        while let Some((snapshot_frame, games, battle)) = self.snapshots.pop_back() {
+            if snapshot_frame <= frame {
+                self.games = games;
+                self.battle = battle;
+                self.frame = snapshot_frame;
+                break;
+            }
+        }
+
+        while self.frame < target {
+            self.simulate_frame();
+        }
+    }
+
+    pub fn simulate_frame(&mut self) {
+        self.snapshots
+            .push_back((self.frame, self.games.clone(), self.battle.clone()));
+
+        for slot in 0..self.games.len() {
+            // a missing remote input is predicted as nothing pressed
+            let input = self.inputs[slot]
+                .get(&self.frame)
+                .copied()
+                .unwrap_or(Input::NONE);
+            self.used[slot].insert(self.frame, input);
+
+            if let Some(targeting) = input.targeting() {
+                self.battle.set_targeting(slot, targeting);
+            }
+
+            let game = &mut self.games[slot];
+            if self.departed[slot].is_some_and(|frame| frame <= self.frame) {
+                game.over = true;
+            }
+            game.apply_input(input);
+            game.tick();
+        }
+        let mut games: Vec<&mut Game> = self.games.iter_mut().collect();
+        self.battle.route_garbage(&mut games);
+
+        self.frame += 1;
+        self.prune();
+    }
+
+    fn prune(&mut self) {
+        let oldest = self.oldest_confirmed().min(self.frame);
+
+        while self
+            .snapshots
+            .front()
+            .is_some_and(|(frame, _, _)| *frame < oldest)
+        {
+            self.snapshots.pop_front();
+        }
+        for used in self.used.iter_mut() {
+            *used = used.split_off(&oldest);
+        }
+        for inputs in self.inputs.iter_mut() {
+            *inputs = inputs.split_off(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::board::Board;
+    use crate::mode::Mode;
+    use crate::tetrimino::Tetrimino;
+
+    const SEED: u64 = 7;
+
+    fn rollback() -> Rollback {
+        let mode = Mode::Versus {
+            garbage_delay: Duration::ZERO,
+        };
+        let mut games: Vec<Game> = (0..2)
+            .map(|_| Game::new(Board::new(10, 40, 1.0, 0.0, 0.0), mode, SEED))
+            .collect();
+
+        // the remote player has a tetris ready, a vertical I over a four deep well, with
+        // a row left under it so it's no perfect clear
+        let board = &mut games[1].board;
+        for _ in 0..4 {
+            board.insert_garbage_row(9);
+        }
+        board.insert_garbage_row(0);
+        board.set_current_tetrimino(&Tetrimino::I, 7, 10, 1);
+
+        Rollback::new(games, Battle::new(2, SEED), 0, 0)
+    }
+
+    // the second drop lands after the tetris, so the garbage it sent rises under it
+    fn local_input(frame: u32) -> Input {
+        match frame {
+            3 => Input::LEFT,
+            8 | 15 | 30 => Input::HARD_DROP,
+            _ => Input::NONE,
+        }
+    }
+
+    fn remote_input(frame: u32) -> Input {
+        match frame {
+            5 => Input::RIGHT | Input::ROTATE_RIGHT,
+            12 | 25 => Input::HARD_DROP,
+            _ => Input::NONE,
+        }
+    }
+
+    // everything the games could disagree on
+    fn state(rollback: &Rollback) -> Vec<(String, u32, u32, bool)> {
+        rollback
+            .games
+            .iter()
+            .map(|game| {
+                let board: String = game.board.snapshot().iter().map(|c| c.to_char()).collect();
+                (board, game.score, game.pieces, game.over)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn late_inputs_resimulate_to_the_same_state() {
+        const FRAMES: u32 = 40;
+
+        // every input known before its frame is simulated
+        let mut straight = rollback();
+        for frame in 0..FRAMES {
+            straight.add_local_input(local_input(frame));
+            straight.add_remote_input(1, frame, remote_input(frame));
+            straight.simulate_frame();
+        }
+
+        // the remote inputs arrive in a batch, ten frames late
+        let mut late = rollback();
+        let mut rolled_back = false;
+        for frame in 0..FRAMES {
+            late.add_local_input(local_input(frame));
+            late.simulate_frame();
+
+            if frame % 10 == 9 {
+                let mut rollback_to = None;
+                for remote in frame - 9..=frame {
+                    let wrong = late.add_remote_input(1, remote, remote_input(remote));
+                    rollback_to = rollback_to.or(wrong);
+                }
+                if let Some(rollback_to) = rollback_to {
+                    late.rollback_and_resimulate(rollback_to);
+                    rolled_back = true;
+                }
+            }
+        }
+
+        // the rolled back frames cleared lines and routed garbage through the battle
+        assert_eq!(straight.games[1].lines, 4);
+        assert_eq!(straight.games[0].board.count_garbage_rows(), 4);
+        assert!(rolled_back);
+        assert_eq!(late.frame, straight.frame);
+        assert_eq!(state(&late), state(&straight));
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    collections::BTreeMap,
+    io::BufReader,
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use rand::Rng;
+
+use super::net::{self, Message, RoomInfo, PROTOCOL_VERSION};
+
+pub const MAX_PLAYERS: usize = 16;
+// a client that takes longer than this to take a message is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Member {
+    id: u64,
+    // drained by the client's writer thread, so nothing blocks under the lobby lock
+    outbox: Sender<Message>,
+    alive: bool,
+}
+
+struct Room {
+    capacity: usize,
+    members: Vec<Member>,
+    started: bool,
+}
+
+impl Room {
+    fn broadcast(&mut self, message: &Message, except: Option<u64>) {
+        for member in self.members.iter_mut() {
+            if Some(member.id) != except {
+                // a member that can't be written to will be cleaned up by its own thread
+                let _ = member.outbox.send(message.clone());
+            }
+        }
+    }
+
+    fn slot_of(&self, id: u64) -> Option<usize> {
+        self.members.iter().position(|member| member.id == id)
+    }
+
+    fn start(&mut self) {
+        let seed = rand::thread_rng().gen();
+        let players = self.members.len();
+        for (slot, member) in self.members.iter().enumerate() {
+            let _ = member.outbox.send(Message::Start {
+                seed,
+                players,
+                slot,
+            });
+        }
+        self.started = true;
+    }
+
+    fn pick_target(&self, attacker: usize) -> Option<usize> {
+        let targets: Vec<usize> = (0..self.members.len())
+            .filter(|&slot| slot != attacker && self.members[slot].alive)
+            .collect();
+        if targets.is_empty() {
+            return None;
+        }
+        Some(targets[rand::thread_rng().gen_range(0, targets.len())])
+    }
+
+    // returns true once the match is decided and the room can go
+    fn knock_out(&mut self, slot: usize) -> bool {
+        self.members[slot].alive = false;
+
+        let alive: Vec<usize> = (0..self.members.len())
+            .filter(|&slot| self.members[slot].alive)
+            .collect();
+        if alive.len() > 1 {
+            return false;
+        }
+
+        if let Some(&winner) = alive.first() {
+            self.broadcast(&Message::Winner(winner), None);
+        }
+        true
+    }
+}
+
+#[derive(Default)]
+struct Lobby {
+    rooms: BTreeMap<String, Room>,
+    next_id: u64,
+    next_quick: u64,
+}
+
+impl Lobby {
+    fn room_list(&self) -> Vec<RoomInfo> {
+        self.rooms
+            .iter()
+            .map(|(name, room)| RoomInfo {
+                name: name.clone(),
+                players: room.members.len(),
+                capacity: room.capacity,
+                started: room.started,
+            })
+            .collect()
+    }
+
+    fn enter(
+        &mut self,
+        id: u64,
+        outbox: Sender<Message>,
+        name: String,
+        capacity: usize,
+    ) -> Result<()> {
+        if !(2..=MAX_PLAYERS).contains(&capacity) {
+            bail!("Rooms hold between 2 and {} players", MAX_PLAYERS);
+        }
+
+        let room = self.rooms.entry(name.clone()).or_insert_with(|| Room {
+            capacity,
+            members: Vec::new(),
+            started: false,
+        });
+        if room.started || room.members.len() >= room.capacity {
+            bail!("Room {} is full", name);
+        }
+
+        room.members.push(Member {
+            id,
+            outbox,
+            alive: true,
+        });
+        println!(
+            "Client {} entered {} ({}/{})",
+            id,
+            name,
+            room.members.len(),
+            room.capacity
+        );
+
+        let joined = Message::Joined {
+            room: name,
+            players: room.members.len(),
+            capacity: room.capacity,
+        };
+        room.broadcast(&joined, None);
+
+        if room.members.len() == room.capacity {
+            room.start();
+        }
+        Ok(())
+    }
+
+    fn is_member(&self, id: u64, name: &str) -> bool {
+        self.rooms
+            .get(name)
+            .is_some_and(|room| room.slot_of(id).is_some())
+    }
+
+    fn quick_room(&mut self) -> String {
+        let waiting = self
+            .rooms
+            .iter()
+            .find(|(_, room)| !room.started && room.members.len() < room.capacity);
+        if let Some((name, _)) = waiting {
+            return name.clone();
+        }
+
+        self.next_quick += 1;
+        format!("quick-{}", self.next_quick)
+    }
+
+    fn leave(&mut self, id: u64, name: &str) {
+        let Some(room) = self.rooms.get_mut(name) else {
+            return;
+        };
+        let Some(slot) = room.slot_of(id) else {
+            return;
+        };
+
+        let finished = if room.started {
+            // slots stay fixed once a match starts, leaving counts as topping out
+            room.broadcast(
+                &Message::From {
+                    slot,
+                    message: Box::new(Message::Bye),
+                },
+                Some(id),
+            );
+            room.knock_out(slot)
+        } else {
+            room.members.remove(slot);
+            let joined = Message::Joined {
+                room: name.to_string(),
+                players: room.members.len(),
+                capacity: room.capacity,
+            };
+            room.broadcast(&joined, None);
+            room.members.is_empty()
+        };
+
+        if finished {
+            self.rooms.remove(name);
+        }
+    }
+}
+
+pub fn run(addr: &str) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Lobby server listening on {}", listener.local_addr()?);
+
+    let lobby = Arc::new(Mutex::new(Lobby::default()));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let lobby = lobby.clone();
+        let id = {
+            let mut lobby = lobby.lock().unwrap();
+            lobby.next_id += 1;
+            lobby.next_id
+        };
+
+        std::thread::spawn(move || {
+            if let Err(e) = handle_client(id, stream, &lobby) {
+                eprintln!("Client {}: {}", id, e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn handle_client(id: u64, mut stream: TcpStream, lobby: &Mutex<Lobby>) -> Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    net::expect_hello(&mut reader)?;
+    net::write_message(
+        &mut stream,
+        &Message::Hello {
+            version: PROTOCOL_VERSION,
+        },
+    )?;
+
+    let outbox = spawn_writer(stream);
+    let mut room: Option<String> = None;
+    let result = client_loop(id, &outbox, &mut reader, lobby, &mut room);
+
+    if let Some(room) = &room {
+        lobby.lock().unwrap().leave(id, room);
+    }
+    result
+}
+
+// sends whatever the client is owed on a thread of its own, a stalled client only stalls that
+fn spawn_writer(mut stream: TcpStream) -> Sender<Message> {
+    let (outbox, messages) = mpsc::channel::<Message>();
+    std::thread::spawn(move || {
+        for message in messages {
+            if net::write_message(&mut stream, &message).is_err() {
+                // wakes the client's reader so it leaves its room
+                let _ = stream.shutdown(Shutdown::Both);
+                break;
+            }
+        }
+    });
+    outbox
+}
+
+fn client_loop(
+    id: u64,
+    outbox: &Sender<Message>,
+    reader: &mut BufReader<TcpStream>,
+    lobby: &Mutex<Lobby>,
+    room: &mut Option<String>,
+) -> Result<()> {
+    loop {
+        // capped, a client can't make the server buffer an endless line
+        let message = net::read_message(reader)?;
+        let mut lobby = lobby.lock().unwrap();
+        // a finished match removes its room for everyone in it, not just whoever ended it
+        if room
+            .as_deref()
+            .is_some_and(|name| !lobby.is_member(id, name))
+        {
+            *room = None;
+        }
+
+        match message {
+            Message::List => outbox.send(Message::Rooms(lobby.room_list()))?,
+            Message::Enter { .. } | Message::Quick if room.is_some() => {
+                outbox.send(Message::Error(String::from("Already in a room")))?
+            }
+            Message::Enter {
+                room: name,
+                capacity,
+            } => match lobby.enter(id, outbox.clone(), name.clone(), capacity) {
+                Ok(()) => *room = Some(name),
+                Err(e) => outbox.send(Message::Error(e.to_string()))?,
+            },
+            Message::Quick => {
+                let name = lobby.quick_room();
+                match lobby.enter(id, outbox.clone(), name.clone(), 2) {
+                    Ok(()) => *room = Some(name),
+                    Err(e) => outbox.send(Message::Error(e.to_string()))?,
+                }
+            }
+            Message::Bye => return Ok(()),
+
+            message @ (Message::Garbage(_)
+            | Message::Board { .. }
+            | Message::ToppedOut
+            | Message::Input { .. }) => {
+                let Some(name) = room.as_deref() else {
+                    continue;
+                };
+                let Some(current) = lobby.rooms.get_mut(name) else {
+                    continue;
+                };
+                let Some(slot) = current.slot_of(id) else {
+                    continue;
+                };
+                if !current.started {
+                    continue;
+                }
+
+                let relayed = Message::From {
+                    slot,
+                    message: Box::new(message.clone()),
+                };
+                match message {
+                    Message::Garbage(_) => {
+                        if let Some(target) = current.pick_target(slot) {
+                            let _ = current.members[target].outbox.send(relayed);
+                        }
+                    }
+                    Message::ToppedOut => {
+                        current.broadcast(&relayed, Some(id));
+                        if current.knock_out(slot) {
+                            lobby.rooms.remove(name);
+                            *room = None;
+                        }
+                    }
+                    _ => current.broadcast(&relayed, Some(id)),
+                }
+            }
+            message => bail!("Unexpected message: {:?}", message),
+        }
+    }
+}
@@ -0,0 +1,694 @@
+use super::ai::Ai;
+use super::animation::Animator;
+use super::battle::{Battle, Targeting};
+use super::board::NEXT_PIECES;
+use super::game::Game;
+use super::input::Input;
+use super::net::{Connection, Message};
+use super::renderer::{
+    data::Instance,
+    text,
+    tiles::{self, TileCache},
+};
+use super::replay::Recorder;
+use super::rollback::Rollback;
+use super::spectate::Broadcaster;
+
+// tiles of room the layouts leave right of a board for its HUD
+pub const HUD_COLUMNS: usize = 6;
+// the HUD starts level with the top of a 20 row playfield
+const HUD_TOP_ROW: usize = 20;
+// the next queue and held piece are drawn above the HUD text, in blocks this fraction of its size
+const PREVIEW_BLOCK: f32 = 0.7;
+// a label and a row of pieces, in text sizes, once for the queue and once for hold
+const PREVIEW_ROW: f32 = 1.5 + 2.0 * PREVIEW_BLOCK + 0.5;
+const PREVIEW_HEIGHT: f32 = 2.0 * PREVIEW_ROW;
+
+pub enum Controller {
+    Human,
+    Ai(Ai),
+    // driven by board snapshots from this slot on the other side of `Session::connection`
+    Remote(usize),
+}
+
+pub struct Player {
+    pub name: String,
+    pub game: Game,
+    pub controller: Controller,
+}
+
+pub struct Session {
+    pub players: Vec<Player>,
+    pub winner: Option<usize>,
+    // the last players topped out on the same frame
+    pub draw: bool,
+    pub connection: Option<Connection>,
+    battle: Battle,
+    // with rollback every game is simulated locally from everyone's inputs
+    rollback: Option<Rollback>,
+    pending_input: Input,
+    reported_over: bool,
+    broadcaster: Option<Broadcaster>,
+    // what the game being watched last reported
+    watched_status: Option<String>,
+    // one per player, when animations are on
+    animators: Option<Vec<Animator>>,
+    // what the local player presses, for playing back later
+    recorder: Option<Recorder>,
+    tiles: TileCache,
+}
+
+impl Session {
+    pub fn new(players: Vec<Player>, seed: u64) -> Self {
+        Self {
+            battle: Battle::new(players.len(), seed),
+            players,
+            winner: None,
+            draw: false,
+            connection: None,
+            rollback: None,
+            pending_input: Input::NONE,
+            reported_over: false,
+            broadcaster: None,
+            watched_status: None,
+            animators: None,
+            recorder: None,
+            tiles: TileCache::default(),
+        }
+    }
+
+    pub fn with_animations(mut self, particles: f32) -> Self {
+        self.animators = Some(
+            self.players
+                .iter()
+                .map(|_| Animator::new(particles))
+                .collect(),
+        );
+        self
+    }
+
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    pub fn with_broadcaster(mut self, broadcaster: Broadcaster) -> Self {
+        self.broadcaster = Some(broadcaster);
+        self
+    }
+
+    pub fn with_connection(mut self, connection: Connection) -> Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    pub fn with_rollback(mut self, input_delay: u32) -> Self {
+        let Some(connection) = &self.connection else {
+            return self;
+        };
+
+        let mut games: Vec<(usize, Game)> = self
+            .players
+            .iter()
+            .map(|player| match player.controller {
+                Controller::Remote(slot) => (slot, player.game.clone()),
+                _ => (connection.slot, player.game.clone()),
+            })
+            .collect();
+        games.sort_by_key(|(slot, _)| *slot);
+        let games: Vec<Game> = games.into_iter().map(|(_, game)| game).collect();
+        let battle = Battle::new(games.len(), connection.seed);
+
+        self.rollback = Some(Rollback::new(games, battle, connection.slot, input_delay));
+        self
+    }
+
+    // queued until the next frame so local play and rollback see inputs the same way
+    pub fn press(&mut self, input: Input) {
+        self.pending_input.press(input);
+    }
+
+    pub fn set_targeting(&mut self, player: usize, targeting: Targeting) {
+        self.battle.set_targeting(player, targeting);
+    }
+
+    fn slot_of(&self, player: usize) -> usize {
+        match self.players[player].controller {
+            Controller::Remote(slot) => slot,
+            _ => self
+                .connection
+                .as_ref()
+                .map_or(player, |connection| connection.slot),
+        }
+    }
+
+    // every game has finished, including the winner's
+    pub fn is_over(&self) -> bool {
+        self.players.iter().all(|player| player.game.over)
+    }
+
+    pub fn is_versus(&self) -> bool {
+        self.players.len() > 1
+    }
+
+    // won by someone or drawn, nothing more can happen
+    pub fn is_decided(&self) -> bool {
+        self.winner.is_some() || self.draw
+    }
+
+    pub fn tick(&mut self) {
+        if self.rollback.is_some() {
+            self.tick_rollback();
+        } else {
+            self.tick_local();
+        }
+        self.broadcast();
+
+        if let Some(animators) = &mut self.animators {
+            for (animator, player) in animators.iter_mut().zip(&self.players) {
+                animator.update(&player.game);
+            }
+        }
+    }
+
+    fn tick_local(&mut self) {
+        let pending_input = std::mem::take(&mut self.pending_input);
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(pending_input);
+        }
+        for (i, player) in self.players.iter_mut().enumerate() {
+            let input = match &mut player.controller {
+                Controller::Remote(_) => continue,
+                Controller::Ai(_) if player.game.over => Input::NONE,
+                Controller::Ai(ai) => ai.tick(&player.game.board),
+                Controller::Human => pending_input,
+            };
+            if let Some(targeting) = input.targeting() {
+                self.battle.set_targeting(i, targeting);
+            }
+            player.game.apply_input(input);
+            player.game.tick();
+        }
+        self.process_events();
+        self.sync_connection();
+
+        if self.players[0].game.over {
+            self.stop_recording();
+        }
+    }
+
+    fn tick_rollback(&mut self) {
+        if self.is_decided() {
+            return;
+        }
+        let (Some(rollback), Some(connection)) = (&mut self.rollback, &mut self.connection) else {
+            return;
+        };
+
+        // a direct connection has a single opponent and doesn't tag its messages
+        let direct_slot = 1 - connection.slot.min(1);
+        let mut resimulate_from: Option<u32> = None;
+        for message in connection.poll() {
+            let (slot, message) = match message {
+                Message::From { slot, message } => (slot, *message),
+                message => (direct_slot, message),
+            };
+            let frame = match message {
+                Message::Input { frame, input } => rollback.add_remote_input(slot, frame, input),
+                Message::Bye => rollback.remove_player(slot),
+                _ => None,
+            };
+            if let Some(frame) = frame {
+                resimulate_from = Some(resimulate_from.map_or(frame, |from| from.min(frame)));
+            }
+        }
+        if let Some(frame) = resimulate_from {
+            rollback.rollback_and_resimulate(frame);
+        }
+
+        // wait for the others when they fall too far behind
+        if rollback.can_advance() {
+            rollback.add_local_input(std::mem::take(&mut self.pending_input));
+            rollback.simulate_frame();
+        }
+
+        let mut disconnected = false;
+        for (frame, input) in rollback.take_unsent() {
+            if connection.send(&Message::Input { frame, input }).is_err() {
+                disconnected = true;
+                break;
+            }
+        }
+
+        let confirmed = rollback.confirmed_games();
+        let alive: Vec<usize> = (0..confirmed.len())
+            .filter(|&slot| !confirmed[slot].over)
+            .collect();
+        let local_over = confirmed[connection.slot].over;
+        let winner = if alive.len() == 1 {
+            Some(alive[0])
+        } else {
+            None
+        };
+        let decided = winner.is_some() || alive.is_empty();
+
+        // only confirmed state decides the match, predictions may still change
+        let games = if decided || disconnected {
+            confirmed.to_vec()
+        } else {
+            rollback.games.clone()
+        };
+
+        if local_over && !self.reported_over {
+            if winner != Some(connection.slot) {
+                // lets the lobby server clean up the room
+                let _ = connection.send(&Message::ToppedOut);
+            }
+            self.reported_over = true;
+        }
+
+        for i in 0..self.players.len() {
+            let slot = self.slot_of(i);
+            self.players[i].game = games[slot].clone();
+            if winner == Some(slot) {
+                self.winner = Some(i);
+            }
+            if decided || disconnected {
+                self.players[i].game.over = true;
+            }
+        }
+        self.draw = alive.is_empty();
+    }
+
+    fn sync_connection(&mut self) {
+        let Some(connection) = &mut self.connection else {
+            return;
+        };
+        let Some(local) = self
+            .players
+            .iter()
+            .position(|player| !matches!(player.controller, Controller::Remote(_)))
+        else {
+            self.watch();
+            return;
+        };
+
+        for message in connection.poll() {
+            // a direct connection has a single opponent and doesn't tag its messages
+            let (slot, message) = match message {
+                Message::From { slot, message } => (Some(slot), *message),
+                message => (None, message),
+            };
+            let remote = self
+                .players
+                .iter()
+                .position(|player| match player.controller {
+                    Controller::Remote(remote_slot) => slot.is_none_or(|slot| slot == remote_slot),
+                    _ => false,
+                });
+
+            match message {
+                Message::Garbage(lines) => self.players[local].game.receive_garbage(lines),
+                Message::Board {
+                    width,
+                    height,
+                    cells,
+                } => {
+                    if let Some(remote) = remote {
+                        let board = &mut self.players[remote].game.board;
+                        if width == board.width() && height == board.height() {
+                            board.load_snapshot(&cells);
+                        }
+                    }
+                }
+                Message::ToppedOut | Message::Bye => match (slot, remote) {
+                    (Some(_), Some(remote)) => self.players[remote].game.over = true,
+                    (None, _) => {
+                        for player in self.players.iter_mut() {
+                            if let Controller::Remote(_) = player.controller {
+                                player.game.over = true;
+                            }
+                        }
+                    }
+                    _ => (),
+                },
+                Message::Winner(slot) => {
+                    self.winner = if slot == connection.slot {
+                        Some(local)
+                    } else {
+                        self.players.iter().position(|player| {
+                            matches!(player.controller, Controller::Remote(remote_slot) if remote_slot == slot)
+                        })
+                    };
+                    for player in self.players.iter_mut() {
+                        player.game.over = true;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let game = &mut self.players[local].game;
+        let mut messages = Vec::new();
+        let attack = game.take_outgoing();
+        if attack > 0 {
+            messages.push(Message::Garbage(attack));
+        }
+        if !self.reported_over {
+            messages.push(Message::Board {
+                width: game.board.width(),
+                height: game.board.height(),
+                cells: game.board.snapshot(),
+            });
+        }
+        if game.over && !self.reported_over {
+            if self.winner != Some(local) {
+                messages.push(Message::ToppedOut);
+            }
+            self.reported_over = true;
+        }
+
+        for message in messages {
+            if connection.send(&message).is_err() {
+                for player in self.players.iter_mut() {
+                    if let Controller::Remote(_) = player.controller {
+                        player.game.over = true;
+                    }
+                }
+                break;
+            }
+        }
+        self.process_events();
+    }
+
+    // spectators only ever receive boards and the status line
+    fn watch(&mut self) {
+        let Some(connection) = &mut self.connection else {
+            return;
+        };
+
+        for message in connection.poll() {
+            match message {
+                Message::From { slot, message } => {
+                    let Message::Board {
+                        width,
+                        height,
+                        cells,
+                    } = *message
+                    else {
+                        continue;
+                    };
+                    let board = self
+                        .players
+                        .iter_mut()
+                        .find(|player| matches!(player.controller, Controller::Remote(remote_slot) if remote_slot == slot))
+                        .map(|player| &mut player.game.board);
+                    if let Some(board) = board {
+                        if width == board.width() && height == board.height() {
+                            board.load_snapshot(&cells);
+                        }
+                    }
+                }
+                Message::Status(text) => self.watched_status = Some(text),
+                Message::Bye => {
+                    let status = self.watched_status.take().unwrap_or_default();
+                    self.watched_status = Some(format!("Broadcast over - {}", status));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn broadcast(&self) {
+        let Some(broadcaster) = &self.broadcaster else {
+            return;
+        };
+
+        for (slot, player) in self.players.iter().enumerate() {
+            let board = &player.game.board;
+            broadcaster.publish(&Message::From {
+                slot,
+                message: Box::new(Message::Board {
+                    width: board.width(),
+                    height: board.height(),
+                    cells: board.snapshot(),
+                }),
+            });
+        }
+        broadcaster.publish(&Message::Status(self.status()));
+    }
+
+    pub fn process_events(&mut self) {
+        // the rollback owns the real games, the players only hold copies
+        if self.rollback.is_some() {
+            return;
+        }
+
+        for player in self.players.iter_mut() {
+            player.game.process_events();
+        }
+
+        if !self.is_versus() {
+            return;
+        }
+
+        // online without rollback, attacks go over the connection instead
+        if self.connection.is_none() && !self.is_decided() {
+            let mut games: Vec<&mut Game> = self
+                .players
+                .iter_mut()
+                .map(|player| &mut player.game)
+                .collect();
+            self.battle.route_garbage(&mut games);
+        }
+
+        let alive: Vec<usize> = (0..self.players.len())
+            .filter(|&i| !self.players[i].game.over)
+            .collect();
+        if !self.is_decided() {
+            match alive.as_slice() {
+                [winner] => {
+                    self.winner = Some(*winner);
+                    self.players[*winner].game.over = true;
+                }
+                [] => self.draw = true,
+                _ => (),
+            }
+        }
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(mut recorder) = self.recorder.take() {
+            recorder.finish();
+        }
+    }
+
+    pub fn disconnect(&mut self) {
+        if let Some(mut connection) = self.connection.take() {
+            // the other side treats a dropped connection the same way
+            let _ = connection.send(&Message::Bye);
+        }
+    }
+
+    pub fn to_instances(&self) -> Vec<Instance> {
+        let mut instances = Vec::new();
+
+        for (i, player) in self.players.iter().enumerate() {
+            let board = &player.game.board;
+            match self.animators.as_ref().map(|animators| &animators[i]) {
+                Some(animator) => {
+                    let mut tiles = self.tiles.board_instances(i, board);
+                    tiles.extend(animator.tiles(board));
+                    instances.extend(shaken(tiles, animator.shake(board)));
+                }
+                None => instances.extend(self.tiles.board_instances(i, board)),
+            }
+        }
+        instances.extend(self.preview_instances());
+
+        instances
+    }
+
+    // effects over the boards, then the HUD text
+    pub fn overlay_instances(&self) -> Vec<Instance> {
+        let mut instances = Vec::new();
+
+        if let Some(animators) = &self.animators {
+            for (animator, player) in animators.iter().zip(&self.players) {
+                let board = &player.game.board;
+                instances.extend(shaken(animator.overlay(board), animator.shake(board)));
+            }
+        }
+        instances.extend(self.hud_instances());
+
+        instances
+    }
+
+    // where a player's HUD starts and how big its text is, for those that have one
+    fn hud_layout(&self, player: usize) -> Option<(Vec<String>, f32, f32, f32)> {
+        // boards in the battle grid are too small for text
+        if player > 0 && self.players.len() > 2 {
+            return None;
+        }
+        let lines = self.hud_lines(player)?;
+
+        let board = &self.players[player].game.board;
+        let block_size = board.block_size();
+        let (left, bottom) = board.origin();
+        let longest = lines.iter().map(String::len).max().unwrap_or(1).max(1);
+        let room = (HUD_COLUMNS as f32 - 0.5) * block_size;
+        let height = HUD_TOP_ROW as f32 * block_size;
+        let size = (block_size * 0.5)
+            .min(room / text::text_width("M", 1.0) / longest as f32)
+            .min(room / (NEXT_PIECES * 5) as f32 / PREVIEW_BLOCK)
+            .min(height / (PREVIEW_HEIGHT + 1.5 * lines.len() as f32));
+
+        let x = left + (board.width() as f32 + 0.5) * block_size;
+        Some((lines, x, bottom + height, size))
+    }
+
+    fn hud_instances(&self) -> Vec<Instance> {
+        let mut instances = Vec::new();
+
+        for i in 0..self.players.len() {
+            let Some((lines, x, top, size)) = self.hud_layout(i) else {
+                continue;
+            };
+
+            instances.extend(text::text_instances("Next", x, top, size));
+            instances.extend(text::text_instances(
+                "Hold",
+                x,
+                top - PREVIEW_ROW * size,
+                size,
+            ));
+
+            let mut y = top - PREVIEW_HEIGHT * size;
+            for line in lines {
+                instances.extend(text::text_instances(&line, x, y, size));
+                y -= size * 1.5;
+            }
+        }
+
+        instances
+    }
+
+    // the next queue and held piece under their labels
+    fn preview_instances(&self) -> Vec<Instance> {
+        let mut instances = Vec::new();
+
+        for (i, player) in self.players.iter().enumerate() {
+            let Some((_, x, top, size)) = self.hud_layout(i) else {
+                continue;
+            };
+
+            let board = &player.game.board;
+            let block = size * PREVIEW_BLOCK;
+            let y = top - 1.5 * size;
+            for (k, piece) in board.next_pieces().enumerate() {
+                let left = x + k as f32 * 5.0 * block;
+                instances.extend(tiles::piece_instances(piece, left, y, block));
+            }
+            if let Some(held) = board.held() {
+                instances.extend(tiles::piece_instances(
+                    &held,
+                    x,
+                    y - PREVIEW_ROW * size,
+                    block,
+                ));
+            }
+        }
+
+        instances
+    }
+
+    // the text shown next to a player's board
+    pub fn hud_lines(&self, player: usize) -> Option<Vec<String>> {
+        let player = &self.players[player];
+        // without rollback a remote game only mirrors the board, its numbers mean nothing
+        if matches!(player.controller, Controller::Remote(_)) && self.rollback.is_none() {
+            return None;
+        }
+
+        let mut lines = Vec::new();
+        if self.is_versus() {
+            lines.push(player.name.clone());
+            lines.push(String::new());
+        }
+        for (label, value) in player.game.hud() {
+            lines.push(label.to_string());
+            lines.push(value);
+            lines.push(String::new());
+        }
+        Some(lines)
+    }
+
+    // the battle in use and where the local player sits in it
+    fn local_battle(&self) -> (&Battle, usize) {
+        match &self.rollback {
+            Some(rollback) => (&rollback.battle, self.slot_of(0)),
+            None => (&self.battle, 0),
+        }
+    }
+
+    fn player_at(&self, index: usize) -> Option<&Player> {
+        match self.rollback {
+            Some(_) => (0..self.players.len())
+                .find(|&i| self.slot_of(i) == index)
+                .map(|i| &self.players[i]),
+            None => self.players.get(index),
+        }
+    }
+
+    pub fn status(&self) -> String {
+        if let Some(status) = &self.watched_status {
+            return format!("[Spectating] {}", status);
+        }
+        if !self.is_versus() {
+            return self.players[0].game.status();
+        }
+
+        let mut status = if self.players.len() > 2 {
+            let (battle, local) = self.local_battle();
+            let alive = self
+                .players
+                .iter()
+                .filter(|player| !player.game.over)
+                .count();
+            let target = battle
+                .target(local)
+                .and_then(|target| self.player_at(target))
+                .map_or("-", |player| &player.name);
+            format!(
+                "{}: {} | {}/{} alive | {} -> {} | Badges {}",
+                self.players[0].name,
+                self.players[0].game.status(),
+                alive,
+                self.players.len(),
+                battle.targeting(local).name(),
+                target,
+                battle.badges[local]
+            )
+        } else {
+            self.players
+                .iter()
+                .map(|player| format!("{}: {}", player.name, player.game.status()))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        };
+        if let Some(winner) = self.winner {
+            status = format!("{} wins! {}", self.players[winner].name, status);
+        } else if self.draw {
+            status = format!("Draw! {}", status);
+        }
+        status
+    }
+}
+
+fn shaken(mut instances: Vec<Instance>, (x, y): (f32, f32)) -> Vec<Instance> {
+    for instance in instances.iter_mut() {
+        instance.position[0] += x;
+        instance.position[1] += y;
+    }
+    instances
+}
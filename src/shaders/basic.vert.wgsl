@@ -2,31 +2,50 @@ struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) uv: vec2<f32>,
 }
+struct InstanceInput {
+    @location(2) offset: vec2<f32>,
+    @location(3) size: vec2<f32>,
+    @location(4) cell: u32,
+    @location(5) tint: vec3<f32>,
+    @location(6) alpha: f32,
+    @location(7) pattern: u32,
+}
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) uv: vec2<f32>,
+    @location(1) tint: vec4<f32>,
+    // across the instance from its top left, whatever atlas cell it shows
+    @location(2) local: vec2<f32>,
+    @location(3) @interpolate(flat) pattern: u32,
 }
 
-@group(0) @binding(2) var<uniform> size: vec2<f32>;
+// world units to clip space, fitted to the window by `Renderer::resize`
+@group(0) @binding(2) var<uniform> scale: vec2<f32>;
+// columns and rows of cells in the bound atlas
+@group(0) @binding(3) var<uniform> atlas: vec2<f32>;
 
 
 @vertex
 fn vs_main(
     in: VertexInput,
+    instance: InstanceInput,
 ) -> VertexOutput {
     var out: VertexOutput;
 
-    var scale = 1.25;
-
-    var aspect = size.x / size.y;
-
+    let position = instance.offset + in.position.xy * instance.size;
     out.position = vec4(
-        in.position.x * scale,
-        in.position.y * aspect * scale,
+        position.x * scale.x,
+        position.y * scale.y,
         in.position.z,
         1.0,
     );
-    out.uv = in.uv;
+
+    let columns = u32(atlas.x);
+    let cell = vec2(f32(instance.cell % columns), f32(instance.cell / columns));
+    out.uv = (cell + in.uv) / atlas;
+    out.tint = vec4(instance.tint, instance.alpha);
+    out.local = in.uv;
+    out.pattern = instance.pattern;
 
     return out;
-}
\ No newline at end of file
+}
@@ -1,12 +1,57 @@
 struct FragInput {
     @builtin(position) position: vec4<f32>,
     @location(0) uv: vec2<f32>,
+    @location(1) tint: vec4<f32>,
+    @location(2) local: vec2<f32>,
+    @location(3) @interpolate(flat) pattern: u32,
 }
 
 @group(0) @binding(0) var t: texture_2d<f32>;
 @group(0) @binding(1) var s: sampler;
 
+// how much the marks darken the tile under them
+let PATTERN_SHADE: f32 = 0.45;
+
+// whether the piece's mark covers this point of the tile, `pattern_covers` in software.rs too
+fn pattern_covers(pattern: u32, uv: vec2<f32>) -> bool {
+    let d = abs(uv - vec2(0.5, 0.5));
+    let inside = max(d.x, d.y) < 0.3;
+    let falling = abs(uv.x - uv.y) < 0.08;
+    let rising = abs(uv.x + uv.y - 1.0) < 0.08;
+    if (pattern == 1u) {
+        // dot
+        return length(d) < 0.18;
+    } else if (pattern == 2u) {
+        return inside && falling;
+    } else if (pattern == 3u) {
+        return inside && rising;
+    } else if (pattern == 4u) {
+        // two bars across
+        return inside && abs(d.y - 0.15) < 0.06;
+    } else if (pattern == 5u) {
+        // plus
+        return max(d.x, d.y) < 0.3 && min(d.x, d.y) < 0.07;
+    } else if (pattern == 6u) {
+        // square ring
+        return max(d.x, d.y) < 0.25 && max(d.x, d.y) > 0.13;
+    } else if (pattern == 7u) {
+        // two bars down
+        return inside && abs(d.x - 0.15) < 0.06;
+    } else if (pattern == 8u) {
+        return inside && (falling || rising);
+    }
+    return false;
+}
+
 @fragment
 fn fs_main( in: FragInput) -> @location(0) vec4<f32> {
-    return textureSample(t, s, in.uv);
-}
\ No newline at end of file
+    var color = textureSample(t, s, in.uv);
+    // the font atlas is transparent around the glyphs
+    if (color.a < 0.5) {
+        discard;
+    }
+    if (pattern_covers(in.pattern, in.local)) {
+        color = vec4(color.rgb * PATTERN_SHADE, color.a);
+    }
+    return color * in.tint;
+}
@@ -0,0 +1,31 @@
+// brightness a color has to pass before it glows
+let BLOOM_THRESHOLD: f32 = 0.6;
+let BLOOM_STRENGTH: f32 = 0.7;
+// taps each way from the pixel, and pixels between them
+let BLOOM_RADIUS: i32 = 4;
+let BLOOM_SPREAD: f32 = 2.0;
+
+fn bright(uv: vec2<f32>) -> vec3<f32> {
+    let color = sample_frame(uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    return color * smoothstep(BLOOM_THRESHOLD, 1.0, brightness);
+}
+
+@fragment
+fn fs_main(in: PostInput) -> @location(0) vec4<f32> {
+    let color = sample_frame(in.uv);
+    let step = BLOOM_SPREAD / resolution;
+    let sigma = f32(BLOOM_RADIUS) / 2.0;
+
+    var glow = vec3(0.0, 0.0, 0.0);
+    var total = 0.0;
+    for (var x: i32 = -BLOOM_RADIUS; x <= BLOOM_RADIUS; x = x + 1) {
+        for (var y: i32 = -BLOOM_RADIUS; y <= BLOOM_RADIUS; y = y + 1) {
+            let offset = vec2(f32(x), f32(y));
+            let weight = exp(-dot(offset, offset) / (2.0 * sigma * sigma));
+            glow = glow + bright(in.uv + offset * step) * weight;
+            total = total + weight;
+        }
+    }
+    return vec4(color.rgb + glow / total * BLOOM_STRENGTH, color.a);
+}
@@ -0,0 +1,26 @@
+// put in front of every post-processing effect, which only has to add its `fs_main`
+struct PostInput {
+    @builtin(position) position: vec4<f32>,
+    // across the frame from its top left
+    @location(0) uv: vec2<f32>,
+}
+
+// the frame as the effects before this one left it
+@group(0) @binding(0) var frame: texture_2d<f32>;
+@group(0) @binding(1) var frame_sampler: sampler;
+// of the frame, in pixels
+@group(0) @binding(2) var<uniform> resolution: vec2<f32>;
+
+// one triangle big enough to cover the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> PostInput {
+    var out: PostInput;
+    let uv = vec2(f32((index << 1u) & 2u), f32(index & 2u));
+    out.position = vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn sample_frame(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(frame, frame_sampler, uv, 0.0);
+}
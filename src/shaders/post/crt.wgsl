@@ -0,0 +1,21 @@
+// how far the picture bulges out in the middle
+let CRT_CURVATURE: f32 = 0.08;
+// pixels the red and blue are pulled apart by at the edges
+let CRT_FRINGE: f32 = 1.5;
+
+@fragment
+fn fs_main(in: PostInput) -> @location(0) vec4<f32> {
+    let centred = in.uv * 2.0 - 1.0;
+    let bent = centred * (1.0 + CRT_CURVATURE * dot(centred, centred));
+    let uv = bent * 0.5 + 0.5;
+
+    let fringe = bent * CRT_FRINGE / resolution;
+    let red = sample_frame(uv + fringe).r;
+    let middle = sample_frame(uv);
+    let blue = sample_frame(uv - fringe).b;
+
+    // black past the glass
+    let inside = all(abs(bent) <= vec2(1.0, 1.0));
+    let color = vec3(red, middle.g, blue);
+    return vec4(select(vec3(0.0, 0.0, 0.0), color, inside), 1.0);
+}
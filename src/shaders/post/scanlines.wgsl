@@ -0,0 +1,12 @@
+// how much darker the gaps between the lines are
+let SCANLINE_DARKNESS: f32 = 0.3;
+// pixels from one line to the next
+let SCANLINE_SPACING: f32 = 3.0;
+
+@fragment
+fn fs_main(in: PostInput) -> @location(0) vec4<f32> {
+    let color = sample_frame(in.uv);
+    let gap = fract(in.position.y / SCANLINE_SPACING) > 0.6;
+    let shade = select(1.0, 1.0 - SCANLINE_DARKNESS, gap);
+    return vec4(color.rgb * shade, color.a);
+}
@@ -0,0 +1,12 @@
+let VIGNETTE_STRENGTH: f32 = 0.6;
+// from the middle of the frame, where the darkening starts and where it's strongest
+let VIGNETTE_INNER: f32 = 0.4;
+let VIGNETTE_OUTER: f32 = 0.85;
+
+@fragment
+fn fs_main(in: PostInput) -> @location(0) vec4<f32> {
+    let color = sample_frame(in.uv);
+    let distance = length(in.uv - vec2(0.5, 0.5));
+    let shade = 1.0 - VIGNETTE_STRENGTH * smoothstep(VIGNETTE_INNER, VIGNETTE_OUTER, distance);
+    return vec4(color.rgb * shade, color.a);
+}
@@ -0,0 +1,359 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use super::palette::Palette;
+use super::renderer::check_effect;
+
+// a skin directory holds this manifest next to its atlas image, one `key = value` per line:
+//
+//   atlas = blocks.png
+//   columns = 10          cells across the atlas, square cells when left out
+//   rows = 1
+//   background = 0.1 0.1 0.15
+//   background_image = stars.png    stretched over the whole window
+//   blue = 0              the atlas cell for each kind of tile, see `CELL_NAMES`
+//   frame = 9             drawn around every board, no frame when left out
+//   grid = 10             stretched along grid lines when they're on, none when left out
+//   empty = none          any cell can be left undrawn
+//   post = bloom, crt     effects the frame goes through in order, built in or .wgsl files
+pub const MANIFEST: &str = "skin.txt";
+
+// names for the cells `tiles::cell` hands out, in order
+pub const CELL_NAMES: [&str; 11] = [
+    "blue",
+    "red",
+    "green",
+    "orange",
+    "purple",
+    "yellow",
+    "light_blue",
+    "garbage",
+    "empty",
+    "frame",
+    "grid",
+];
+
+// the built-in atlas's cells, with a plain one added for the frame and grid
+const BUILTIN_COLUMNS: u32 = 9;
+const BUILTIN_SOLID: [u8; 4] = [150, 150, 160, 255];
+// close to black, the built-in empty cells are black
+const BUILTIN_BACKGROUND: [f32; 3] = [0.01, 0.01, 0.015];
+
+pub struct Skin {
+    pub atlas: image::DynamicImage,
+    pub columns: u32,
+    pub rows: u32,
+    // the atlas cell each of `CELL_NAMES` is drawn with, none isn't drawn at all
+    pub cells: Vec<Option<u32>>,
+    pub background: [f32; 3],
+    pub background_image: Option<image::DynamicImage>,
+    // a mark on each piece's tiles so they can be told apart without their colors
+    pub patterns: bool,
+    // post-processing effects by name, or paths to .wgsl files, drawn without any when empty
+    pub post: Vec<String>,
+}
+
+// what the command line changes about whichever skin is loaded, kept through hot reloads
+#[derive(Clone, Default)]
+pub struct Overrides {
+    pub background: Option<String>,
+    pub palette: Palette,
+    pub patterns: bool,
+    pub post: Option<Vec<String>>,
+}
+
+impl Skin {
+    pub fn builtin() -> Self {
+        let bytes = include_bytes!("images/tetris-atlas.png");
+        let atlas = image::load_from_memory(bytes).expect("Built-in atlas is a valid image");
+        Self::from_builtin_atlas(atlas)
+    }
+
+    // the built-in atlas, or a changed copy of it while working on the game
+    pub fn from_builtin_atlas(atlas: image::DynamicImage) -> Self {
+        let cell_width = atlas.width() / BUILTIN_COLUMNS;
+        let mut extended = image::RgbaImage::from_pixel(
+            cell_width * (BUILTIN_COLUMNS + 1),
+            atlas.height(),
+            image::Rgba(BUILTIN_SOLID),
+        );
+        image::imageops::replace(&mut extended, &atlas.to_rgba8(), 0, 0);
+
+        let solid = Some(BUILTIN_COLUMNS);
+        let mut cells: Vec<Option<u32>> = (0..BUILTIN_COLUMNS).map(Some).collect();
+        cells.extend([solid, solid]);
+
+        Self {
+            atlas: image::DynamicImage::ImageRgba8(extended),
+            columns: BUILTIN_COLUMNS + 1,
+            rows: 1,
+            cells,
+            background: BUILTIN_BACKGROUND,
+            background_image: None,
+            patterns: false,
+            post: Vec::new(),
+        }
+    }
+
+    pub fn load(dir: &Path) -> Result<Self> {
+        let manifest_path = dir.join(MANIFEST);
+        let manifest = fs::read_to_string(&manifest_path)
+            .with_context(|| format!("Couldn't read {}", manifest_path.display()))?;
+
+        let builtin = Self::builtin();
+        let mut atlas_file = None;
+        let mut columns = None;
+        let mut rows = 1;
+        // the built-in frame and grid are made up, skins bring their own or go without
+        let mut cells = builtin.cells;
+        cells[CELL_NAMES.len() - 2..].fill(None);
+        let mut background = builtin.background;
+        let mut background_image = None;
+        let mut post = Vec::new();
+
+        for (number, line) in manifest.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Line {}: expected `key = value`", number + 1))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "atlas" => atlas_file = Some(value.to_string()),
+                "columns" => columns = Some(value.parse()?),
+                "rows" => rows = value.parse()?,
+                "background" => background = parse_color(value)?,
+                "background_image" => background_image = Some(load_image(&dir.join(value))?),
+                // files are next to the manifest
+                "post" => {
+                    post = parse_effects(value)
+                        .into_iter()
+                        .map(|effect| {
+                            if effect.ends_with(".wgsl") {
+                                dir.join(effect).to_string_lossy().into_owned()
+                            } else {
+                                effect
+                            }
+                        })
+                        .collect()
+                }
+                _ => {
+                    let index = CELL_NAMES
+                        .iter()
+                        .position(|name| *name == key)
+                        .ok_or_else(|| anyhow!("Line {}: unknown key {}", number + 1, key))?;
+                    cells[index] = match value {
+                        "none" => None,
+                        _ => Some(value.parse()?),
+                    };
+                }
+            }
+        }
+
+        for effect in &post {
+            check_effect(effect)?;
+        }
+
+        let atlas_file = atlas_file.ok_or_else(|| anyhow!("{} doesn't name an atlas", MANIFEST))?;
+        let atlas = load_image(&dir.join(atlas_file))?;
+
+        let (width, height) = (atlas.width(), atlas.height());
+        let columns = columns.unwrap_or(width * rows / height.max(1));
+        if columns == 0 || rows == 0 {
+            bail!("The atlas needs at least one cell");
+        }
+        for (name, cell) in CELL_NAMES.iter().zip(&cells) {
+            if let Some(cell) = cell {
+                if *cell >= columns * rows {
+                    bail!("Cell {} for {} is outside the atlas", cell, name);
+                }
+            }
+        }
+
+        Ok(Self {
+            atlas,
+            columns,
+            rows,
+            cells,
+            background,
+            background_image,
+            patterns: false,
+            post,
+        })
+    }
+
+    // a color as three numbers from 0 to 1, or an image to stretch over the window
+    pub fn set_background(&mut self, value: &str) -> Result<()> {
+        if let Ok(color) = parse_color(value) {
+            self.background = color;
+            self.background_image = None;
+        } else {
+            self.background_image = Some(load_image(Path::new(value))?);
+        }
+        Ok(())
+    }
+
+    // each piece's cell painted in the palette's color instead, keeping the cell's shading
+    pub fn set_palette(&mut self, palette: Palette) {
+        let Some(colors) = palette.colors() else {
+            return;
+        };
+
+        // a cell used for anything else too gets a copy for this piece alone, or the last color wins
+        let shared = (0..colors.len())
+            .filter(|&i| {
+                self.cells[i].is_some()
+                    && (self.cells.iter().enumerate())
+                        .any(|(j, cell)| j != i && *cell == self.cells[i])
+            })
+            .collect::<Vec<_>>();
+        self.copy_cells(&shared);
+
+        let mut atlas = self.atlas.to_rgba8();
+        let cell_width = atlas.width() / self.columns;
+        let cell_height = atlas.height() / self.rows;
+        for (cell, color) in self.cells.iter().zip(colors) {
+            let Some(cell) = cell else {
+                continue;
+            };
+            let left = (cell % self.columns) * cell_width;
+            let top = (cell / self.columns) * cell_height;
+            let texels = (0..cell_height)
+                .flat_map(|y| (0..cell_width).map(move |x| (left + x, top + y)))
+                .collect::<Vec<_>>();
+
+            // lighter and darker than the cell's average, the same amount as before
+            let opaque = texels
+                .iter()
+                .map(|&(x, y)| atlas.get_pixel(x, y).0)
+                .filter(|texel| texel[3] >= 128)
+                .collect::<Vec<_>>();
+            let average = opaque.iter().map(|texel| luminance(*texel)).sum::<f32>()
+                / opaque.len().max(1) as f32;
+            for (x, y) in texels {
+                let texel = &mut atlas.get_pixel_mut(x, y).0;
+                let shade = if average > 0.0 {
+                    luminance(*texel) / average
+                } else {
+                    1.0
+                };
+                for (channel, color) in texel.iter_mut().zip(color) {
+                    *channel = (color as f32 * shade).round().min(255.0) as u8;
+                }
+            }
+        }
+        self.atlas = image::DynamicImage::ImageRgba8(atlas);
+    }
+
+    // the cells of these `CELL_NAMES` copied into new rows under the atlas and used from there
+    fn copy_cells(&mut self, names: &[usize]) {
+        if names.is_empty() {
+            return;
+        }
+
+        let atlas = self.atlas.to_rgba8();
+        let cell_width = atlas.width() / self.columns;
+        let cell_height = atlas.height() / self.rows;
+        let extra_rows = (names.len() as u32).div_ceil(self.columns);
+        // anything past the last whole cell is left behind so the cells stay the same size
+        let mut extended = image::RgbaImage::new(
+            cell_width * self.columns,
+            cell_height * (self.rows + extra_rows),
+        );
+        image::imageops::replace(&mut extended, &atlas, 0, 0);
+
+        let position = |cell: u32| {
+            (
+                (cell % self.columns) * cell_width,
+                (cell / self.columns) * cell_height,
+            )
+        };
+        for (k, &name) in names.iter().enumerate() {
+            let Some(cell) = self.cells[name] else {
+                continue;
+            };
+            let copy = self.columns * self.rows + k as u32;
+            let (x, y) = position(cell);
+            let source =
+                image::imageops::crop_imm(&atlas, x, y, cell_width, cell_height).to_image();
+            let (x, y) = position(copy);
+            image::imageops::replace(&mut extended, &source, x as i64, y as i64);
+            self.cells[name] = Some(copy);
+        }
+
+        self.rows += extra_rows;
+        self.atlas = image::DynamicImage::ImageRgba8(extended);
+    }
+
+    pub fn apply(&mut self, overrides: &Overrides) -> Result<()> {
+        self.set_palette(overrides.palette);
+        self.patterns = overrides.patterns;
+        // one bad option still leaves the other in place
+        let post = match &overrides.post {
+            Some(post) => self.set_post(post),
+            None => Ok(()),
+        };
+        let background = match &overrides.background {
+            Some(background) => self.set_background(background),
+            None => Ok(()),
+        };
+        post.and(background)
+    }
+
+    fn set_post(&mut self, post: &[String]) -> Result<()> {
+        for effect in post {
+            check_effect(effect)?;
+        }
+        self.post = post.to_vec();
+        Ok(())
+    }
+
+    // a broken skin shouldn't keep the game from starting
+    pub fn load_or_builtin(dir: Option<&str>) -> Self {
+        let Some(dir) = dir else {
+            return Self::builtin();
+        };
+
+        match Self::load(Path::new(dir)) {
+            Ok(skin) => skin,
+            Err(e) => {
+                eprintln!("Skin {}: {:#}, using the built-in one", dir, e);
+                Self::builtin()
+            }
+        }
+    }
+}
+
+fn parse_color(value: &str) -> Result<[f32; 3]> {
+    let channels = value
+        .split_whitespace()
+        .map(|channel| channel.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match channels[..] {
+        [r, g, b] => Ok([r, g, b]),
+        _ => bail!("Colors are three numbers from 0 to 1"),
+    }
+}
+
+// a comma separated list, `none` for no effects at all
+pub fn parse_effects(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|effect| !effect.is_empty() && *effect != "none")
+        .map(String::from)
+        .collect()
+}
+
+fn luminance([r, g, b, _]: [u8; 4]) -> f32 {
+    0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32
+}
+
+fn load_image(path: &Path) -> Result<image::DynamicImage> {
+    image::open(path).with_context(|| format!("Couldn't load {}", path.display()))
+}
@@ -0,0 +1,119 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufReader, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+
+use super::net::{self, Message, PROTOCOL_VERSION};
+
+// a spectator that can't keep up is dropped rather than stalling the game, once this
+// many lines are waiting for it
+const BACKLOG: usize = 4096;
+// and its writer gives up on a socket that stops taking anything at all
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Audience {
+    players: usize,
+    // drained by each spectator's writer thread, the game only ever queues
+    spectators: Vec<SyncSender<String>>,
+    // the latest line of each kind, replayed to late joiners
+    latest: BTreeMap<String, String>,
+}
+
+pub struct Broadcaster {
+    audience: Arc<Mutex<Audience>>,
+}
+
+impl Broadcaster {
+    pub fn bind(addr: &str, players: usize) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        println!("Spectators can watch on {}", listener.local_addr()?);
+
+        let audience = Arc::new(Mutex::new(Audience {
+            players,
+            spectators: Vec::new(),
+            latest: BTreeMap::new(),
+        }));
+
+        let shared = audience.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let audience = shared.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = welcome(stream, &audience) {
+                        eprintln!("Spectator: {}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(Self { audience })
+    }
+
+    // only sends what changed since the last time
+    pub fn publish(&self, message: &Message) {
+        let line = message.encode();
+        // one line is kept per board and per kind of message
+        let key = match message {
+            Message::From { .. } => line.splitn(4, ' ').take(3).collect::<Vec<_>>().join(" "),
+            _ => line.split(' ').next().unwrap_or_default().to_string(),
+        };
+
+        let mut audience = self.audience.lock().unwrap();
+        if audience.latest.get(&key) == Some(&line) {
+            return;
+        }
+        // a full backlog or a writer that quit both mean the spectator is gone
+        audience
+            .spectators
+            .retain(|outbox| outbox.try_send(line.clone()).is_ok());
+        audience.latest.insert(key, line);
+    }
+}
+
+fn welcome(mut stream: TcpStream, audience: &Mutex<Audience>) -> Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    net::expect_hello(&mut reader)?;
+    net::write_message(
+        &mut stream,
+        &Message::Hello {
+            version: PROTOCOL_VERSION,
+        },
+    )?;
+
+    // queued under the lock, so no frame slips in between the catch-up and the stream
+    let (outbox, lines) = mpsc::sync_channel(BACKLOG);
+    {
+        let mut audience = audience.lock().unwrap();
+        let watch = Message::Watch {
+            players: audience.players,
+        };
+        let catch_up = std::iter::once(watch.encode()).chain(audience.latest.values().cloned());
+        for line in catch_up {
+            if outbox.try_send(line).is_err() {
+                bail!("Too much to catch up on");
+            }
+        }
+        audience.spectators.push(outbox);
+    }
+
+    println!("Spectator {} is watching", stream.peer_addr()?);
+    for line in lines {
+        if writeln!(stream, "{}", line).is_err() {
+            break;
+        }
+    }
+    // whether it fell behind or went away
+    let _ = stream.shutdown(Shutdown::Both);
+    Ok(())
+}
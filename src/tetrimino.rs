@@ -1,6 +1,6 @@
 use rand::Rng;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 #[allow(dead_code)]
 pub enum Color {
     Empty,
@@ -11,6 +11,38 @@ pub enum Color {
     Yellow,
     Purple,
     Green,
+    Garbage,
+}
+
+impl Color {
+    pub fn to_char(self) -> char {
+        match self {
+            Color::Empty => '.',
+            Color::LightBlue => 'I',
+            Color::Blue => 'J',
+            Color::Red => 'Z',
+            Color::Orange => 'L',
+            Color::Yellow => 'O',
+            Color::Purple => 'T',
+            Color::Green => 'S',
+            Color::Garbage => 'G',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        Some(match c {
+            '.' => Color::Empty,
+            'I' => Color::LightBlue,
+            'J' => Color::Blue,
+            'Z' => Color::Red,
+            'L' => Color::Orange,
+            'O' => Color::Yellow,
+            'T' => Color::Purple,
+            'S' => Color::Green,
+            'G' => Color::Garbage,
+            _ => return None,
+        })
+    }
 }
 
 #[allow(dead_code)]
@@ -26,8 +58,8 @@ pub enum Tetrimino {
 }
 
 impl Tetrimino {
-    pub fn random() -> Self {
-        let rand = rand::thread_rng().gen_range(0, 7);
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let rand = rng.gen_range(0, 7);
         use Tetrimino::*;
         match rand {
             0 => L,
@@ -97,6 +129,21 @@ impl Tetrimino {
         }
     }
 
+    // the spawn orientation as (column, row) from the top left of its bounding box
+    pub fn preview(&self) -> Vec<(i32, i32)> {
+        let length = self.get_length();
+        let cells: Vec<(i32, i32)> = self
+            .get_blocks(0)
+            .iter()
+            .enumerate()
+            .filter(|(_, &block)| block != 0)
+            .map(|(i, _)| (i as i32 % length, i as i32 / length))
+            .collect();
+        let left = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+        let top = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+        cells.iter().map(|&(x, y)| (x - left, y - top)).collect()
+    }
+
     pub fn get_color(&self) -> Color {
         match self {
             Tetrimino::I => Color::LightBlue,
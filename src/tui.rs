@@ -0,0 +1,266 @@
+use std::{
+    io::{self, Stdout, Write},
+    time::Duration,
+};
+
+use anyhow::Result;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    execute, queue,
+    style::{self, Stylize},
+    terminal::{self, ClearType},
+};
+
+use super::battle::Targeting;
+use super::board::Board;
+use super::frontend::{Command, Frontend};
+use super::input::Input;
+use super::session::Session;
+use super::tetrimino::{Color, Tetrimino};
+
+// characters right of each board for its HUD
+const HUD_WIDTH: usize = 14;
+// four blocks of two characters, the widest a piece gets
+const PREVIEW_WIDTH: usize = 8;
+
+// the same session the window plays, drawn with colored text instead
+pub struct Terminal {
+    stdout: Stdout,
+    last_lines: Vec<String>,
+    paused: bool,
+}
+
+impl Terminal {
+    pub fn new() -> Result<Self> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self {
+            stdout,
+            last_lines: Vec::new(),
+            paused: false,
+        })
+    }
+}
+
+// puts the terminal back however the game ends
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl Frontend for Terminal {
+    fn draw(&mut self, session: &Session) -> Result<()> {
+        let (columns, _) = terminal::size()?;
+        let mut lines = session_lines(session, columns as usize);
+        if self.paused {
+            lines.push(String::from("Paused - Esc to resume, Ctrl-C to quit"));
+        }
+        // terminals are slow, only redraw when something changed
+        if lines == self.last_lines {
+            return Ok(());
+        }
+
+        queue!(self.stdout, cursor::MoveTo(0, 0))?;
+        for line in &lines {
+            queue!(
+                self.stdout,
+                style::Print(line),
+                terminal::Clear(ClearType::UntilNewLine),
+                style::Print("\r\n")
+            )?;
+        }
+        queue!(self.stdout, terminal::Clear(ClearType::FromCursorDown))?;
+        self.stdout.flush()?;
+        self.last_lines = lines;
+        Ok(())
+    }
+
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Command>> {
+        if !event::poll(timeout)? {
+            return Ok(None);
+        }
+        match event::read()? {
+            Event::Key(key) => Ok(key_command(key)),
+            _ => Ok(None),
+        }
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+}
+
+// the same keys as the window, terminals only say when one goes down
+fn key_command(key: KeyEvent) -> Option<Command> {
+    let input = match key.code {
+        KeyCode::Esc => return Some(Command::Pause),
+        // raw mode keeps ctrl-c from interrupting
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            return Some(Command::Quit)
+        }
+        KeyCode::Char(c) => match c.to_ascii_lowercase() {
+            'q' => Input::ROTATE_LEFT,
+            'e' => Input::ROTATE_RIGHT,
+            'a' => Input::LEFT,
+            'd' => Input::RIGHT,
+            's' => Input::SOFT_DROP,
+            ' ' => Input::HARD_DROP,
+            'c' => Input::HOLD,
+            '1' => Input::target(Targeting::Random),
+            '2' => Input::target(Targeting::Attackers),
+            '3' => Input::target(Targeting::KOs),
+            '4' => Input::target(Targeting::Badges),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(Command::Press(input))
+}
+
+// every board with its HUD, as many across as fit, and the status line under them
+fn session_lines(session: &Session, columns: usize) -> Vec<String> {
+    let panels: Vec<(usize, Vec<String>)> = (0..session.players.len())
+        .map(|i| player_lines(session, i))
+        .collect();
+    let panel_width = panels.first().map_or(1, |(width, _)| *width);
+    let per_row = (columns / panel_width).max(1);
+
+    let mut lines = Vec::new();
+    for row in panels.chunks(per_row) {
+        let height = row.iter().map(|(_, lines)| lines.len()).max().unwrap_or(0);
+        for y in 0..height {
+            lines.push(
+                row.iter()
+                    .map(|(width, lines)| match lines.get(y) {
+                        Some(line) => line.clone(),
+                        None => " ".repeat(*width),
+                    })
+                    .collect(),
+            );
+        }
+    }
+    lines.push(String::new());
+    lines.push(session.status());
+    lines
+}
+
+// one player's board, next queue and hold, and HUD side by side, with how wide they are on screen
+fn player_lines(session: &Session, player: usize) -> (usize, Vec<String>) {
+    let board = &session.players[player].game.board;
+    let board_width = board.width() * 2 + 2;
+    let board_lines = board_lines(board);
+    let hud = session.hud_lines(player);
+    // mirrored boards have no queue to show, like they have no HUD
+    let preview = match hud {
+        Some(_) => preview_lines(board),
+        None => Vec::new(),
+    };
+    let hud = hud.unwrap_or_default();
+
+    let height = board_lines.len().max(preview.len()).max(hud.len());
+    let lines = (0..height)
+        .map(|y| {
+            let board_line = match board_lines.get(y) {
+                Some(line) => line.clone(),
+                None => " ".repeat(board_width),
+            };
+            let preview_line = match preview.get(y) {
+                Some(line) => line.clone(),
+                None => " ".repeat(PREVIEW_WIDTH),
+            };
+            let hud_line: String = hud
+                .get(y)
+                .map_or("", String::as_str)
+                .chars()
+                .take(HUD_WIDTH)
+                .collect();
+            format!(
+                "{} {} {:<width$}",
+                board_line,
+                preview_line,
+                hud_line,
+                width = HUD_WIDTH
+            )
+        })
+        .collect();
+    (board_width + 1 + PREVIEW_WIDTH + 1 + HUD_WIDTH, lines)
+}
+
+// the next pieces under one label and the held one under another, each padded to the column
+fn preview_lines(board: &Board) -> Vec<String> {
+    let mut lines = vec![format!("{:<width$}", "Next", width = PREVIEW_WIDTH)];
+    for piece in board.next_pieces() {
+        lines.extend(piece_lines(piece));
+    }
+    lines.push(format!("{:<width$}", "Hold", width = PREVIEW_WIDTH));
+    match board.held() {
+        Some(held) => lines.extend(piece_lines(&held)),
+        None => lines.extend(vec![" ".repeat(PREVIEW_WIDTH); 3]),
+    }
+    lines
+}
+
+// two rows and a blank one under them
+fn piece_lines(piece: &Tetrimino) -> Vec<String> {
+    let cells = piece.preview();
+    let mut lines: Vec<String> = (0..2)
+        .map(|y| {
+            (0..PREVIEW_WIDTH as i32 / 2)
+                .map(|x| {
+                    if cells.contains(&(x, y)) {
+                        "██".with(terminal_color(piece.get_color())).to_string()
+                    } else {
+                        "  ".to_string()
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    lines.push(" ".repeat(PREVIEW_WIDTH));
+    lines
+}
+
+// two characters a block, in a box
+fn board_lines(board: &Board) -> Vec<String> {
+    let width = board.width();
+    let cells = board.snapshot();
+    let rows = board.visible_height();
+
+    let mut lines = vec![format!("┌{}┐", "─".repeat(width * 2))];
+    for y in (0..rows).rev() {
+        let clearing = board.clearing().contains(&y);
+        let mut line = String::from("│");
+        for color in &cells[y * width..(y + 1) * width] {
+            let block = match color {
+                Color::Empty => " .".dark_grey().to_string(),
+                // rows waiting to be cleared flash white
+                _ if clearing => "██".white().to_string(),
+                _ => "██".with(terminal_color(*color)).to_string(),
+            };
+            line.push_str(&block);
+        }
+        line.push('│');
+        lines.push(line);
+    }
+    lines.push(format!("└{}┘", "─".repeat(width * 2)));
+    lines
+}
+
+fn terminal_color(color: Color) -> style::Color {
+    match color {
+        Color::Blue => style::Color::Blue,
+        Color::Red => style::Color::Red,
+        Color::Green => style::Color::Green,
+        // the basic sixteen colors have no orange
+        Color::Orange => style::Color::AnsiValue(208),
+        Color::Purple => style::Color::Magenta,
+        Color::Yellow => style::Color::Yellow,
+        Color::LightBlue => style::Color::Cyan,
+        Color::Garbage => style::Color::Grey,
+        Color::Empty => style::Color::Reset,
+    }
+}